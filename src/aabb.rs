@@ -12,6 +12,15 @@ impl AABB {
         AABB { min, max }
     }
 
+    pub fn centroid(&self) -> Vec3 {
+        0.5 * (self.min + self.max)
+    }
+
+    pub fn surface_area(&self) -> f32 {
+        let d = self.max - self.min;
+        2. * (d.x() * d.y() + d.y() * d.z() + d.z() * d.x())
+    }
+
     pub fn hit(&self, r: &Ray, mut tmin: f32, mut tmax: f32) -> bool {
         for i in 0..3 {
             let invd = 1. / r.direction()[i];
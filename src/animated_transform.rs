@@ -0,0 +1,254 @@
+use crate::aabb::{surrounding_box, AABB};
+use crate::hittable::{HitRecord, Hittable};
+use crate::mat4::Mat4;
+use crate::quaternion::Quat;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::{hit_transformed, pdf_value_transformed, random_transformed, transform_box};
+use crate::vec3::Vec3;
+
+// Boxes sampled per keyframe interval when bounding the motion.
+const BOX_SAMPLES: usize = 64;
+
+// Where an animated object is at one moment: scaled along the axes, then
+// rotated about the origin, then translated.
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe {
+    pub time: f32,
+    pub translation: Vec3,
+    pub rotation: Quat,
+    pub scale: Vec3,
+}
+
+impl Keyframe {
+    // A keyframe at time that leaves the object where it is.
+    pub fn new(time: f32) -> Keyframe {
+        Keyframe {
+            time,
+            translation: Vec3::new(0., 0., 0.),
+            rotation: Quat::identity(),
+            scale: Vec3::new(1., 1., 1.),
+        }
+    }
+}
+
+fn lerp(a: Vec3, b: Vec3, t: f32) -> Vec3 {
+    a + t * (b - a)
+}
+
+// Moves a hittable through a sequence of keyframes, interpolating between
+// them at the time of each ray: translation and scale linearly and rotation
+// by slerp. Before the first keyframe and after the last the object holds
+// still, so a single keyframe places it like a Transform.
+pub struct AnimatedTransform {
+    hittable: Box<dyn Hittable>,
+    keyframes: Vec<Keyframe>,
+}
+
+impl AnimatedTransform {
+    // Panics unless there is at least one keyframe, their times increase, no
+    // scale is 0 along any axis and none changes sign between keyframes,
+    // which would pass through 0 on the way.
+    pub fn new(hittable: impl 'static + Hittable, keyframes: Vec<Keyframe>) -> AnimatedTransform {
+        assert!(!keyframes.is_empty(), "no keyframes");
+        assert!(
+            keyframes.windows(2).all(|pair| pair[0].time < pair[1].time),
+            "keyframe times must increase"
+        );
+        assert!(
+            keyframes.iter().all(|k| (0..3).all(|c| k.scale[c] != 0.)),
+            "keyframe scale is 0"
+        );
+        assert!(
+            keyframes
+                .windows(2)
+                .all(|pair| (0..3).all(|c| pair[0].scale[c] * pair[1].scale[c] > 0.)),
+            "keyframe scale changes sign"
+        );
+        AnimatedTransform {
+            hittable: Box::new(hittable),
+            keyframes,
+        }
+    }
+
+    fn interpolate(&self, time: f32) -> Keyframe {
+        let next = self.keyframes.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keyframes[0];
+        }
+        if next == self.keyframes.len() {
+            return self.keyframes[next - 1];
+        }
+        let (a, b) = (&self.keyframes[next - 1], &self.keyframes[next]);
+        let t = (time - a.time) / (b.time - a.time);
+        Keyframe {
+            time,
+            translation: lerp(a.translation, b.translation, t),
+            rotation: Quat::slerp(a.rotation, b.rotation, t),
+            scale: lerp(a.scale, b.scale, t),
+        }
+    }
+
+    // The matrix placing the object at time, and its inverse, which is found
+    // by undoing each step in reverse rather than by a general inversion.
+    fn matrices(&self, time: f32) -> (Mat4, Mat4) {
+        let k = self.interpolate(time);
+        let matrix = Mat4::translate(k.translation) * k.rotation.to_mat4() * Mat4::scale(k.scale);
+        let inverse = Mat4::scale(Vec3::new(1., 1., 1.) / k.scale)
+            * k.rotation.conjugate().to_mat4()
+            * Mat4::translate(-k.translation);
+        (matrix, inverse)
+    }
+}
+
+impl Hittable for AnimatedTransform {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        let (matrix, inverse) = self.matrices(r.time());
+        hit_transformed(
+            &*self.hittable,
+            &matrix,
+            &inverse,
+            &inverse.transpose(),
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    // The union of the boxes at evenly spaced times through each keyframe
+    // interval, grown by how far any point of the object can move between
+    // two of those times, so that it covers the object over the whole of
+    // [t0, t1] and not just at the samples.
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        let child = self.hittable.bounding_box(t0, t1)?;
+        let mut bbox = transform_box(&self.matrices(t0).0, &child);
+        bbox = surrounding_box(bbox, transform_box(&self.matrices(t1).0, &child));
+
+        // The farthest a point of the child box can be from the origin along
+        // each axis, about which it is scaled and rotated.
+        let mut reach = Vec3::default();
+        for c in 0..3 {
+            reach[c] = child.min[c].abs().max(child.max[c].abs());
+        }
+        let mut pad = 0f32;
+        for pair in self.keyframes.windows(2) {
+            let (a, b) = (&pair[0], &pair[1]);
+            let (start, end) = (a.time.max(t0), b.time.min(t1));
+            if start >= end {
+                continue;
+            }
+            for i in 0..=BOX_SAMPLES {
+                let time = start + (end - start) * i as f32 / BOX_SAMPLES as f32;
+                bbox = surrounding_box(bbox, transform_box(&self.matrices(time).0, &child));
+            }
+
+            // A bound on the distance any point travels over the whole
+            // interval, from the translation, the rotation at its constant
+            // angular speed and the change of scale. Every point is within
+            // half a step's share of it from where it was sampled.
+            let mut largest = Vec3::default();
+            let mut change = Vec3::default();
+            for c in 0..3 {
+                largest[c] = a.scale[c].abs().max(b.scale[c].abs()) * reach[c];
+                change[c] = (b.scale[c] - a.scale[c]).abs() * reach[c];
+            }
+            let travel = (b.translation - a.translation).length()
+                + a.rotation.angle_to(b.rotation) * largest.length()
+                + change.length();
+            let step = (end - start) / BOX_SAMPLES as f32;
+            pad = pad.max(travel * step / (b.time - a.time) / 2.);
+        }
+        let pad = Vec3::new(pad, pad, pad);
+        Some(AABB::new(bbox.min - pad, bbox.max + pad))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.hittable.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let (_, inverse) = self.matrices(time);
+        pdf_value_transformed(&*self.hittable, &inverse, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let (matrix, inverse) = self.matrices(time);
+        random_transformed(&*self.hittable, &matrix, &inverse, o, time, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+
+    fn animated(keyframes: Vec<Keyframe>) -> AnimatedTransform {
+        let mat = Material::Diffuse(Texture::solid((0.5, 0.5, 0.5)));
+        AnimatedTransform::new(Sphere::new(Vec3::new(0., 0., 0.), 1., mat), keyframes)
+    }
+
+    fn keyframe(time: f32, x: f32, angle: f32, scale: f32) -> Keyframe {
+        Keyframe {
+            translation: Vec3::new(x, 0., 0.),
+            rotation: Quat::from_axis_angle((0., 0., 1.), angle),
+            scale: Vec3::new(scale, scale, scale),
+            ..Keyframe::new(time)
+        }
+    }
+
+    fn close(a: &Keyframe, b: &Keyframe) -> bool {
+        (a.translation - b.translation).length() < 1e-5
+            && (a.scale - b.scale).length() < 1e-5
+            && a.rotation.dot(b.rotation).abs() > 1. - 1e-6
+    }
+
+    #[test]
+    fn interpolate_clamps_outside_the_keyframes() {
+        let first = keyframe(1., -2., 0., 1.);
+        let last = keyframe(3., 4., 90., 2.);
+        let anim = animated(vec![first, keyframe(2., 0., 45., 1.5), last]);
+        for time in [-10., 0., 1.] {
+            assert!(close(&anim.interpolate(time), &first), "time {}", time);
+        }
+        for time in [3., 3.5, 100.] {
+            assert!(close(&anim.interpolate(time), &last), "time {}", time);
+        }
+    }
+
+    #[test]
+    fn interpolate_between_keyframes() {
+        let anim = animated(vec![keyframe(0., 0., 0., 1.), keyframe(2., 4., 90., 3.)]);
+        let k = anim.interpolate(0.5);
+        assert!(close(&k, &keyframe(0.5, 1., 22.5, 1.5)));
+        assert_eq!(k.time, 0.5);
+    }
+
+    #[test]
+    fn single_keyframe_holds_still() {
+        let only = keyframe(0.5, 3., 30., 2.);
+        let anim = animated(vec![only]);
+        for time in [0., 0.5, 1.] {
+            assert!(close(&anim.interpolate(time), &only));
+        }
+    }
+
+    // The inverse built step by step undoes the matrix.
+    #[test]
+    fn matrices_are_inverses() {
+        let anim = animated(vec![keyframe(0., 0., 0., 1.), keyframe(1., 4., 120., 0.5)]);
+        let (matrix, inverse) = anim.matrices(0.3);
+        let p = Vec3::new(0.2, -1., 3.);
+        let back = inverse.transform_point(matrix.transform_point(p));
+        assert!((back - p).length() < 1e-5);
+    }
+
+    #[test]
+    #[should_panic(expected = "keyframe scale changes sign")]
+    fn scale_changing_sign_panics() {
+        let mut flipped = keyframe(1., 0., 0., 1.);
+        flipped.scale[1] = -1.;
+        animated(vec![keyframe(0., 0., 0., 1.), flipped]);
+    }
+}
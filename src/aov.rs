@@ -0,0 +1,147 @@
+use serde::{Deserialize, Serialize};
+
+use crate::hittable::HitRecord;
+use crate::tonemap::{saturate, srgb_encode};
+use crate::vec3::Vec3;
+
+// Arbitrary output variables: properties of the first surface seen through
+// each pixel, rendered alongside the radiance for denoising and compositing.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Aov {
+    Albedo,
+    Normal,
+    // Distance along the camera ray.
+    Depth,
+    Position,
+    Uv,
+    // The HitRecord id, or -1 where nothing was hit.
+    Id,
+    // The number of samples taken, which varies with adaptive sampling.
+    Samples,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Albedo,
+        Aov::Normal,
+        Aov::Depth,
+        Aov::Position,
+        Aov::Uv,
+        Aov::Id,
+        Aov::Samples,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::Uv => "uv",
+            Aov::Id => "id",
+            Aov::Samples => "samples",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Aov> {
+        Aov::ALL.iter().copied().find(|aov| aov.name() == name)
+    }
+
+    // Names of the components that are used, as EXR channels. Depth uses the
+    // conventional Z channel.
+    pub fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Albedo => &["albedo.R", "albedo.G", "albedo.B"],
+            Aov::Normal => &["normal.X", "normal.Y", "normal.Z"],
+            Aov::Depth => &["Z"],
+            Aov::Position => &["position.X", "position.Y", "position.Z"],
+            Aov::Uv => &["uv.U", "uv.V"],
+            Aov::Id => &["id"],
+            Aov::Samples => &["samples"],
+        }
+    }
+
+    // Whether samples can be averaged within a pixel. Ids can't, so pixels
+    // keep the id seen by their first sample. Sample counts aren't sampled at
+    // all and are filled in by the renderer.
+    pub fn is_filtered(self) -> bool {
+        self != Aov::Id && self != Aov::Samples
+    }
+
+    // The value for a camera ray with the given first hit. Scalars are
+    // repeated across all three components.
+    pub fn value(self, rec: Option<&HitRecord>) -> Vec3 {
+        let rec = match rec {
+            Some(rec) => rec,
+            None if self == Aov::Id => return Vec3::new(-1., -1., -1.),
+            None => return Vec3::default(),
+        };
+        match self {
+            Aov::Albedo => rec.mat.albedo(rec.u, rec.v, rec.p),
+            Aov::Normal => rec.normal,
+            Aov::Depth => Vec3::new(rec.t, rec.t, rec.t),
+            Aov::Position => rec.p,
+            Aov::Uv => Vec3::new(rec.u, rec.v, 0.),
+            Aov::Id => Vec3::new(rec.id as f32, rec.id as f32, rec.id as f32),
+            Aov::Samples => Vec3::default(),
+        }
+    }
+
+    // An 8-bit rendition of a whole layer for viewing. Depth and position are
+    // normalized to the range found in the layer, ids get arbitrary but
+    // distinct colors and sample counts a heatmap from black through red and
+    // yellow to white.
+    pub fn visualize(self, layer: &[Vec3]) -> Vec<[u8; 3]> {
+        let byte = |x: f32| (saturate(x) * 255. + 0.5) as u8;
+        let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+        for value in layer {
+            for c in 0..3 {
+                min[c] = min[c].min(value[c]);
+                max[c] = max[c].max(value[c]);
+            }
+        }
+        let normalize = |value: Vec3, c: usize| {
+            if max[c] > min[c] {
+                (value[c] - min[c]) / (max[c] - min[c])
+            } else {
+                0.
+            }
+        };
+
+        layer
+            .iter()
+            .map(|&value| match self {
+                Aov::Albedo => {
+                    let encode = |x: f32| byte(srgb_encode(saturate(x)));
+                    [encode(value[0]), encode(value[1]), encode(value[2])]
+                }
+                Aov::Normal => [
+                    byte(0.5 * value[0] + 0.5),
+                    byte(0.5 * value[1] + 0.5),
+                    byte(0.5 * value[2] + 0.5),
+                ],
+                Aov::Depth => {
+                    let depth = byte(normalize(value, 0));
+                    [depth, depth, depth]
+                }
+                Aov::Position => [
+                    byte(normalize(value, 0)),
+                    byte(normalize(value, 1)),
+                    byte(normalize(value, 2)),
+                ],
+                Aov::Uv => [byte(value[0]), byte(value[1]), 0],
+                Aov::Id if value[0] < 0. => [0, 0, 0],
+                Aov::Id => {
+                    let hash = (value[0] as u32 + 1).wrapping_mul(0x9e37_79b9);
+                    [(hash >> 24) as u8, (hash >> 16) as u8, (hash >> 8) as u8]
+                }
+                Aov::Samples => {
+                    let t = 3. * value[0] / max[0].max(1.);
+                    [byte(t), byte(t - 1.), byte(t - 2.)]
+                }
+            })
+            .collect()
+    }
+}
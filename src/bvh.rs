@@ -1,116 +1,509 @@
-use rand::prelude::*;
+use std::fmt;
 
 use crate::aabb::{surrounding_box, AABB};
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
 
-pub enum BVH {
-    Single {
-        left: Box<dyn Hittable>,
+// Relative costs of stepping through an interior node and of intersecting a
+// primitive, used both to choose SAH splits and to estimate tree quality.
+const TRAVERSAL_COST: f32 = 0.125;
+const INTERSECT_COST: f32 = 1.;
+
+const SAH_BUCKETS: usize = 12;
+const MAX_LEAF_SIZE: usize = 4;
+
+// Size of the fixed traversal stack. The builders make a leaf of whatever is
+// left on reaching this depth, so no tree is ever deeper.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SplitMethod {
+    // Split at the median along the axis of largest extent.
+    Median,
+    // Binned surface area heuristic.
+    Sah,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct BVHStats {
+    pub nodes: usize,
+    pub leaves: usize,
+    pub primitives: usize,
+    pub max_depth: usize,
+    // Expected cost of tracing a ray that hits the root, in units of
+    // primitive intersections.
+    pub cost: f32,
+}
+
+impl fmt::Display for BVHStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} nodes ({} leaves) over {} primitives, depth {}, estimated cost {:.2}",
+            self.nodes, self.leaves, self.primitives, self.max_depth, self.cost
+        )
+    }
+}
+
+// The tree is stored as a flat array in depth-first order: an interior node's
+// first child directly follows it and only the index of the second child is
+// kept. Leaves refer to a contiguous run of primitives.
+pub struct BVH {
+    nodes: Vec<LinearNode>,
+    primitives: Vec<Box<dyn Hittable>>,
+    // The index each primitive had in the list the BVH was built from, which
+    // is what hits report as their id.
+    ids: Vec<u32>,
+    lights: Vec<usize>,
+    stats: BVHStats,
+}
+
+struct LinearNode {
+    bbox: AABB,
+    kind: LinearKind,
+}
+
+enum LinearKind {
+    Leaf { first: usize, count: usize },
+    Interior { second_child: usize, axis: usize },
+}
+
+// A primitive paired with its index in the list the BVH is built from.
+type Primitive = (u32, Box<dyn Hittable>);
+
+// Pointer-based tree produced by the builders, flattened once complete.
+enum Node {
+    Leaf {
+        items: Vec<Primitive>,
         bbox: AABB,
     },
-    Double {
-        left: Box<dyn Hittable>,
-        right: Box<dyn Hittable>,
+    Interior {
+        left: Box<Node>,
+        right: Box<Node>,
+        axis: usize,
         bbox: AABB,
     },
 }
 
 impl BVH {
     pub fn new(l: &mut Vec<Box<dyn Hittable>>, time0: f32, time1: f32) -> BVH {
+        BVH::with_split(l, time0, time1, SplitMethod::Median)
+    }
+
+    pub fn with_split(
+        l: &mut Vec<Box<dyn Hittable>>,
+        time0: f32,
+        time1: f32,
+        method: SplitMethod,
+    ) -> BVH {
         // Note: l is emptied by the this function!
         // l must be non-empty.
+        if l.is_empty() {
+            panic!("BVH cannot be created with 0 nodes!");
+        }
 
-        // Pick a random axis and split l in half along it.
-        let axis = thread_rng().gen_range(0, 3);
-        l.sort_unstable_by(|a, b| {
-            let bbox_left = a.bounding_box(0., 0.).expect("No AABB in BVH constructor!");
-            let bbox_right = b.bounding_box(0., 0.).expect("No AABB in BVH constructor!");
+        let mut items: Vec<Primitive> = l
+            .drain(..)
+            .enumerate()
+            .map(|(i, item)| (i as u32, item))
+            .collect();
+        let root = match method {
+            SplitMethod::Median => build_median(&mut items, time0, time1, 1),
+            SplitMethod::Sah => {
+                let items = items
+                    .into_iter()
+                    .map(|item| {
+                        let bbox = item
+                            .1
+                            .bounding_box(time0, time1)
+                            .expect("No AABB in BVH constructor!");
+                        (item, bbox)
+                    })
+                    .collect();
+                build_sah(items, 1)
+            }
+        };
 
-            bbox_left.min[axis]
-                .partial_cmp(&bbox_right.min[axis])
-                .unwrap()
-                .reverse()
-        });
+        let mut stats = BVHStats::default();
+        root.collect_stats(&mut stats, root.bbox().surface_area(), 1);
+        debug_assert!(stats.max_depth <= MAX_DEPTH);
 
-        // The current BVH node will either directly contain one/two hittables, or
-        // it will contain two BVH children nodes.
-        match l.len() {
-            0 => panic!("BVH cannot be created with 0 nodes!"),
-            1 => {
-                let left = l.pop().unwrap();
-                BVH::Single {
-                    bbox: left.bounding_box(time0, time1).unwrap(),
-                    left,
-                }
-            }
-            2 => {
-                let left = l.pop().unwrap();
-                let right = l.pop().unwrap();
-                BVH::Double {
-                    bbox: surrounding_box(
-                        left.bounding_box(time0, time1).unwrap(),
-                        right.bounding_box(time0, time1).unwrap(),
-                    ),
-                    left,
-                    right,
+        let mut bvh = BVH {
+            nodes: Vec::with_capacity(stats.nodes),
+            primitives: Vec::with_capacity(stats.primitives),
+            ids: Vec::with_capacity(stats.primitives),
+            lights: Vec::new(),
+            stats,
+        };
+        bvh.flatten(root);
+        bvh.lights = (0..bvh.primitives.len())
+            .filter(|&i| bvh.primitives[i].is_emissive())
+            .collect();
+        bvh
+    }
+
+    // The emissive primitives, for sampling light sources directly.
+    pub fn lights(&self) -> Vec<&dyn Hittable> {
+        self.lights
+            .iter()
+            .map(|&i| self.primitives[i].as_ref())
+            .collect()
+    }
+
+    fn flatten(&mut self, node: Node) {
+        match node {
+            Node::Leaf { items, bbox } => {
+                let kind = LinearKind::Leaf {
+                    first: self.primitives.len(),
+                    count: items.len(),
+                };
+                self.nodes.push(LinearNode { bbox, kind });
+                for (id, item) in items {
+                    self.ids.push(id);
+                    self.primitives.push(item);
                 }
             }
-            _ => {
-                let rest = &mut l.split_off(l.len() / 2);
-                let left = Box::new(BVH::new(rest, time0, time1));
-                let right = Box::new(BVH::new(l, time0, time1));
-                BVH::Double {
-                    bbox: surrounding_box(
-                        left.bounding_box(time0, time1).unwrap(),
-                        right.bounding_box(time0, time1).unwrap(),
-                    ),
-                    left,
-                    right,
+            Node::Interior {
+                left,
+                right,
+                axis,
+                bbox,
+            } => {
+                let index = self.nodes.len();
+                let kind = LinearKind::Interior {
+                    second_child: 0,
+                    axis,
+                };
+                self.nodes.push(LinearNode { bbox, kind });
+                self.flatten(*left);
+                let second = self.nodes.len();
+                self.flatten(*right);
+                if let LinearKind::Interior { second_child, .. } = &mut self.nodes[index].kind {
+                    *second_child = second;
                 }
             }
         }
     }
+
+    pub fn stats(&self) -> &BVHStats {
+        &self.stats
+    }
 }
 
-impl Hittable for BVH {
-    fn hit(&self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord> {
+// Both builders take the depth of the node they build, the root being at 1.
+fn build_median(l: &mut Vec<Primitive>, time0: f32, time1: f32, depth: usize) -> Node {
+    // Split l in half along the axis its boxes are spread out most on.
+    let bbox = l
+        .iter()
+        .map(|(_, item)| {
+            item.bounding_box(0., 0.)
+                .expect("No AABB in BVH constructor!")
+        })
+        .reduce(surrounding_box)
+        .expect("No AABB in BVH constructor!");
+    let extent = bbox.max - bbox.min;
+    let axis = (0..3)
+        .max_by(|&a, &b| extent[a].partial_cmp(&extent[b]).unwrap())
+        .unwrap();
+    l.sort_unstable_by(|a, b| {
+        let bbox_left =
+            a.1.bounding_box(0., 0.)
+                .expect("No AABB in BVH constructor!");
+        let bbox_right =
+            b.1.bounding_box(0., 0.)
+                .expect("No AABB in BVH constructor!");
+
+        bbox_left.min[axis]
+            .partial_cmp(&bbox_right.min[axis])
+            .unwrap()
+            .reverse()
+    });
+
+    // The current BVH node will either directly contain one/two hittables, or
+    // it will contain two BVH children nodes.
+    if l.len() <= 2 || depth >= MAX_DEPTH {
+        let items = l.drain(..).rev().collect::<Vec<_>>();
+        let bbox = items
+            .iter()
+            .map(|(_, item)| {
+                item.bounding_box(time0, time1)
+                    .expect("No AABB in BVH constructor!")
+            })
+            .reduce(surrounding_box)
+            .unwrap();
+        Node::Leaf { items, bbox }
+    } else {
+        let rest = &mut l.split_off(l.len() / 2);
+        let left = Box::new(build_median(rest, time0, time1, depth + 1));
+        let right = Box::new(build_median(l, time0, time1, depth + 1));
+        Node::Interior {
+            bbox: surrounding_box(left.bbox().clone(), right.bbox().clone()),
+            left,
+            right,
+            axis,
+        }
+    }
+}
+
+fn build_sah(items: Vec<(Primitive, AABB)>, depth: usize) -> Node {
+    let bbox = items
+        .iter()
+        .skip(1)
+        .fold(items[0].1.clone(), |acc, (_, b)| {
+            surrounding_box(acc, b.clone())
+        });
+    let leaf = |items: Vec<(Primitive, AABB)>, bbox| Node::Leaf {
+        items: items.into_iter().map(|(item, _)| item).collect(),
+        bbox,
+    };
+    if items.len() == 1 || depth >= MAX_DEPTH {
+        return leaf(items, bbox);
+    }
+
+    // Bucket primitives by centroid along the axis where centroids spread the
+    // most. If they all coincide there is nothing to split on.
+    let first = items[0].1.centroid();
+    let centroid_bounds = items.iter().fold(AABB::new(first, first), |acc, (_, b)| {
+        surrounding_box(acc, AABB::new(b.centroid(), b.centroid()))
+    });
+    let extent = centroid_bounds.max - centroid_bounds.min;
+    let axis = if extent.x() > extent.y() && extent.x() > extent.z() {
+        0
+    } else if extent.y() > extent.z() {
+        1
+    } else {
+        2
+    };
+    if extent[axis] <= 0. {
+        return leaf(items, bbox);
+    }
+
+    let bucket_of = |b: &AABB| {
+        let offset = (b.centroid()[axis] - centroid_bounds.min[axis]) / extent[axis];
+        ((offset * SAH_BUCKETS as f32) as usize).min(SAH_BUCKETS - 1)
+    };
+    let mut buckets: Vec<(usize, Option<AABB>)> = vec![(0, None); SAH_BUCKETS];
+    for (_, b) in &items {
+        let bucket = &mut buckets[bucket_of(b)];
+        bucket.0 += 1;
+        bucket.1 = Some(match bucket.1.take() {
+            Some(acc) => surrounding_box(acc, b.clone()),
+            None => b.clone(),
+        });
+    }
+
+    // Cost of splitting after each bucket, relative to intersecting
+    // everything in a single leaf.
+    let area = bbox.surface_area();
+    let (split, split_cost) = (0..SAH_BUCKETS - 1)
+        .map(|i| {
+            let side = |range: &[(usize, Option<AABB>)]| {
+                range.iter().fold((0, None), |(n, acc), (count, b)| {
+                    let merged = match (acc, b.clone()) {
+                        (Some(a), Some(b)) => Some(surrounding_box(a, b)),
+                        (a, b) => a.or(b),
+                    };
+                    (n + count, merged)
+                })
+            };
+            let sah =
+                |(n, b): (usize, Option<AABB>)| b.map_or(0., |b: AABB| n as f32 * b.surface_area());
+            let cost = TRAVERSAL_COST
+                + INTERSECT_COST * (sah(side(&buckets[..=i])) + sah(side(&buckets[i + 1..])))
+                    / area;
+            (i, cost)
+        })
+        .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap())
+        .unwrap();
+
+    let leaf_cost = INTERSECT_COST * items.len() as f32;
+    if items.len() <= MAX_LEAF_SIZE && split_cost >= leaf_cost {
+        return leaf(items, bbox);
+    }
+
+    let (left, right): (Vec<_>, Vec<_>) =
+        items.into_iter().partition(|(_, b)| bucket_of(b) <= split);
+    if left.is_empty() || right.is_empty() {
+        let items = left.into_iter().chain(right).collect();
+        return leaf(items, bbox);
+    }
+    Node::Interior {
+        left: Box::new(build_sah(left, depth + 1)),
+        right: Box::new(build_sah(right, depth + 1)),
+        axis,
+        bbox,
+    }
+}
+
+impl Node {
+    fn bbox(&self) -> &AABB {
         match self {
-            BVH::Single { left, bbox } => {
-                if bbox.hit(r, t_min, t_max) {
-                    left.hit(r, t_min, t_max)
-                } else {
-                    None
-                }
+            Node::Leaf { bbox, .. } => bbox,
+            Node::Interior { bbox, .. } => bbox,
+        }
+    }
+
+    fn collect_stats(&self, stats: &mut BVHStats, root_area: f32, depth: usize) {
+        // A node is visited with probability proportional to its surface area.
+        let p = if root_area > 0. {
+            self.bbox().surface_area() / root_area
+        } else {
+            1.
+        };
+        stats.nodes += 1;
+        stats.max_depth = stats.max_depth.max(depth);
+        match self {
+            Node::Leaf { items, .. } => {
+                stats.leaves += 1;
+                stats.primitives += items.len();
+                stats.cost += p * INTERSECT_COST * items.len() as f32;
             }
-            BVH::Double { left, right, bbox } => {
-                if bbox.hit(r, t_min, t_max) {
-                    let left_hit = left.hit(r, t_min, t_max);
-                    let right_hit = right.hit(r, t_min, t_max);
-
-                    match (left_hit, right_hit) {
-                        (Some(left_rec), Some(right_rec)) => {
-                            if left_rec.t < right_rec.t {
-                                Some(left_rec)
-                            } else {
-                                Some(right_rec)
+            Node::Interior { left, right, .. } => {
+                stats.cost += p * TRAVERSAL_COST;
+                left.collect_stats(stats, root_area, depth + 1);
+                right.collect_stats(stats, root_area, depth + 1);
+            }
+        }
+    }
+}
+
+impl Hittable for BVH {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, mut t_max: f32) -> Option<HitRecord<'a>> {
+        // Walk the tree front to back: at each interior node descend first into
+        // the child on the near side of the split axis and defer the other.
+        // Every hit shrinks t_max, so deferred nodes whose boxes lie beyond the
+        // closest hit so far are rejected by their box test.
+        let dir_is_neg = [
+            r.direction().x() < 0.,
+            r.direction().y() < 0.,
+            r.direction().z() < 0.,
+        ];
+        let mut stack = [0; MAX_DEPTH];
+        let mut stack_len = 0;
+        let mut index = 0;
+        let mut closest = None;
+
+        loop {
+            let node = &self.nodes[index];
+            if node.bbox.hit(r, t_min, t_max) {
+                match node.kind {
+                    LinearKind::Leaf { first, count } => {
+                        for (i, item) in self.primitives[first..first + count].iter().enumerate() {
+                            if let Some(rec) = item.hit(r, t_min, t_max) {
+                                t_max = rec.t;
+                                closest = Some(HitRecord {
+                                    id: self.ids[first + i],
+                                    ..rec
+                                });
                             }
                         }
-                        (Some(left_rec), _) => Some(left_rec),
-                        (_, Some(right_rec)) => Some(right_rec),
-                        _ => None,
                     }
-                } else {
-                    None
+                    LinearKind::Interior { second_child, axis } => {
+                        let (near, far) = if dir_is_neg[axis] {
+                            (second_child, index + 1)
+                        } else {
+                            (index + 1, second_child)
+                        };
+                        stack[stack_len] = far;
+                        stack_len += 1;
+                        index = near;
+                        continue;
+                    }
                 }
             }
+
+            if stack_len == 0 {
+                break;
+            }
+            stack_len -= 1;
+            index = stack[stack_len];
         }
+
+        closest
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
-        match self {
-            BVH::Single { bbox, .. } => Some(bbox.clone()),
-            BVH::Double { bbox, .. } => Some(bbox.clone()),
+        Some(self.nodes[0].bbox.clone())
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.lights.is_empty()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let sum = self
+            .lights
+            .iter()
+            .map(|&i| self.primitives[i].pdf_value(o, v, time))
+            .sum::<f32>();
+        sum / self.lights.len() as f32
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let n = self.lights.len();
+        let i = self.lights[((sampler.get_1d() * n as f32) as usize).min(n - 1)];
+        self.primitives[i].random(o, time, sampler)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+
+    fn spheres(n: usize) -> Vec<Box<dyn Hittable>> {
+        (0..n)
+            .map(|i| {
+                let mat = Material::Diffuse(Texture::solid((0.5, 0.5, 0.5)));
+                Box::new(Sphere::new(Vec3::new(i as f32, 0., 0.), 0.25, mat)) as Box<dyn Hittable>
+            })
+            .collect()
+    }
+
+    // Builds below the root as if it were deep in a tree already, where only
+    // two more levels fit.
+    #[test]
+    fn builders_stop_at_max_depth() {
+        let depth = MAX_DEPTH - 1;
+        let primitives = || {
+            spheres(100)
+                .into_iter()
+                .enumerate()
+                .map(|(i, item)| (i as u32, item))
+        };
+        let median = build_median(&mut primitives().collect(), 0., 1., depth);
+        let items = primitives()
+            .map(|item| {
+                let bbox = item.1.bounding_box(0., 1.).unwrap();
+                (item, bbox)
+            })
+            .collect();
+        let sah = build_sah(items, depth);
+
+        for root in [median, sah] {
+            let mut stats = BVHStats::default();
+            root.collect_stats(&mut stats, root.bbox().surface_area(), depth);
+            assert_eq!(stats.max_depth, MAX_DEPTH);
+            assert_eq!(stats.primitives, 100);
+        }
+    }
+
+    #[test]
+    fn ids_follow_the_order_objects_were_given_in() {
+        for method in [SplitMethod::Median, SplitMethod::Sah] {
+            // Reversed so that the order differs from any the builders sort by.
+            let mut l = spheres(50);
+            l.reverse();
+            let bvh = BVH::with_split(&mut l, 0., 1., method);
+            for id in 0..50 {
+                let x = (49 - id) as f32;
+                let r = Ray::new(Vec3::new(x, -1., 0.), Vec3::new(0., 1., 0.), 0.);
+                assert_eq!(bvh.hit(&r, 0., f32::MAX).unwrap().id, id as u32);
+            }
         }
     }
 }
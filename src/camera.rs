@@ -1,6 +1,7 @@
-use rand::prelude::*;
+use serde::Deserialize;
 
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct Camera {
@@ -10,23 +11,30 @@ pub struct Camera {
     pub vertical: Vec3,
     pub u: Vec3,
     pub v: Vec3,
-    pub w: Vec3,
     pub time0: f32,
     pub time1: f32,
     pub lens_radius: f32,
 }
 
-fn random_in_unit_disk() -> Vec3 {
-    let mut p = Vec3::new(1., 1., 1.);
-    let mut rng = thread_rng();
-    while p.squared_length() >= 1. {
-        let v = Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), 0.);
-        p = 2. * v - Vec3::new(1., 1., 0.);
+// Shirley's concentric mapping of the unit square onto the unit disk, which
+// keeps points that are spread out on the square spread out on the disk.
+fn random_in_unit_disk(sampler: &mut dyn Sampler) -> Vec3 {
+    use std::f32::consts::FRAC_PI_4;
+    let (u, v) = sampler.get_2d();
+    let (a, b) = (2. * u - 1., 2. * v - 1.);
+    if a == 0. && b == 0. {
+        return Vec3::default();
     }
-    p
+    let (r, theta) = if a.abs() > b.abs() {
+        (a, FRAC_PI_4 * (b / a))
+    } else {
+        (b, 2. * FRAC_PI_4 - FRAC_PI_4 * (a / b))
+    };
+    Vec3::new(r * theta.cos(), r * theta.sin(), 0.)
 }
 
 impl Camera {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         lookfrom: Vec3,
         lookat: Vec3,
@@ -56,17 +64,16 @@ impl Camera {
             vertical: 2. * half_height * focus_dist * v,
             u,
             v,
-            w,
             time0,
             time1,
             lens_radius: aperture / 2.,
         }
     }
 
-    pub fn get_ray(&self, s: f32, t: f32) -> Ray {
-        let rd = self.lens_radius * random_in_unit_disk();
+    pub fn get_ray(&self, s: f32, t: f32, sampler: &mut dyn Sampler) -> Ray {
+        let rd = self.lens_radius * random_in_unit_disk(sampler);
         let offset = self.u * rd.x() + self.v * rd.y();
-        let time = self.time0 + thread_rng().gen::<f32>() * (self.time1 - self.time0);
+        let time = self.time0 + sampler.get_1d() * (self.time1 - self.time0);
         Ray::new(
             self.origin + offset,
             self.lower_left_corner + s * self.horizontal + t * self.vertical - self.origin - offset,
@@ -74,3 +81,69 @@ impl Camera {
         )
     }
 }
+
+// Camera placement as read from a scene description; the aspect ratio comes
+// from the output resolution.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CameraSettings {
+    pub lookfrom: Vec3,
+    pub lookat: Vec3,
+    #[serde(default = "CameraSettings::default_vup")]
+    pub vup: Vec3,
+    #[serde(default = "CameraSettings::default_vfov")]
+    pub vfov: f32,
+    #[serde(default)]
+    pub aperture: f32,
+    #[serde(default = "CameraSettings::default_focus_dist")]
+    pub focus_dist: f32,
+    #[serde(default)]
+    pub time0: f32,
+    #[serde(default = "CameraSettings::default_time1")]
+    pub time1: f32,
+}
+
+impl CameraSettings {
+    fn default_vup() -> Vec3 {
+        Vec3::new(0., 1., 0.)
+    }
+
+    fn default_vfov() -> f32 {
+        40.
+    }
+
+    fn default_focus_dist() -> f32 {
+        10.
+    }
+
+    fn default_time1() -> f32 {
+        1.
+    }
+
+    pub fn new(lookfrom: impl Into<Vec3>, lookat: impl Into<Vec3>, vfov: f32) -> Self {
+        CameraSettings {
+            lookfrom: lookfrom.into(),
+            lookat: lookat.into(),
+            vup: CameraSettings::default_vup(),
+            vfov,
+            aperture: 0.,
+            focus_dist: CameraSettings::default_focus_dist(),
+            time0: 0.,
+            time1: CameraSettings::default_time1(),
+        }
+    }
+
+    pub fn build(&self, aspect: f32) -> Camera {
+        Camera::new(
+            self.lookfrom,
+            self.lookat,
+            self.vup,
+            self.vfov,
+            aspect,
+            self.aperture,
+            self.focus_dist,
+            self.time0,
+            self.time1,
+        )
+    }
+}
@@ -0,0 +1,231 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+
+use crate::aov::Aov;
+use crate::filter::Filter;
+use crate::sampler::SamplerKind;
+use crate::vec3::Vec3;
+
+const MAGIC: &[u8; 8] = b"RTCKPT05";
+
+#[derive(Debug)]
+pub enum CheckpointError {
+    Io { path: PathBuf, err: io::Error },
+    Corrupt { path: PathBuf, msg: String },
+    // The checkpoint was taken with different render settings.
+    Mismatch(String),
+}
+
+impl fmt::Display for CheckpointError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CheckpointError::Io { path, err } => write!(f, "{}: {}", path.display(), err),
+            CheckpointError::Corrupt { path, msg } => {
+                write!(f, "{}: not a valid checkpoint: {}", path.display(), msg)
+            }
+            CheckpointError::Mismatch(msg) => write!(f, "checkpoint doesn't match: {}", msg),
+        }
+    }
+}
+
+impl Error for CheckpointError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            CheckpointError::Io { err, .. } => Some(err),
+            _ => None,
+        }
+    }
+}
+
+// The state of an unfinished render: the running sums and sample count of
+// every pixel, and the settings that determine the random numbers of the
+// samples still to come. Nothing about the scene is recorded, so resuming
+// with a different scene goes unnoticed.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub(crate) width: u32,
+    pub(crate) height: u32,
+    pub(crate) max_depth: i32,
+    pub(crate) sampler: SamplerKind,
+    // The samples per pixel the render was set up for.
+    pub(crate) pattern_samples: u32,
+    pub(crate) seed: u64,
+    pub(crate) aovs: Vec<Aov>,
+    pub(crate) radiance: Vec<Vec3>,
+    pub(crate) luminance_sq: Vec<f32>,
+    pub(crate) samples: Vec<u32>,
+    pub(crate) filter: Filter,
+    // Filter-weighted sums of radiance, and of the weights, in the renderer's
+    // fixed point.
+    pub(crate) filtered: Vec<[i64; 3]>,
+    pub(crate) weights: Vec<i64>,
+    // One sum per pixel for each of aovs.
+    pub(crate) aov_sums: Vec<Vec<Vec3>>,
+}
+
+impl Checkpoint {
+    // Writes to a temporary file first, so that being killed midway leaves
+    // any previous checkpoint intact.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), CheckpointError> {
+        let path = path.as_ref();
+        let io_error = |err| CheckpointError::Io {
+            path: path.to_owned(),
+            err,
+        };
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+
+        let mut w = BufWriter::new(File::create(&tmp).map_err(io_error)?);
+        w.write_all(MAGIC).map_err(io_error)?;
+        bincode::serialize_into(&mut w, self).map_err(|err| match *err {
+            bincode::ErrorKind::Io(err) => io_error(err),
+            err => io_error(io::Error::other(err.to_string())),
+        })?;
+        w.into_inner()
+            .map_err(|err| io_error(err.into_error()))?
+            .sync_all()
+            .map_err(io_error)?;
+        fs::rename(&tmp, path).map_err(io_error)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Checkpoint, CheckpointError> {
+        let path = path.as_ref();
+        let corrupt = |msg: String| CheckpointError::Corrupt {
+            path: path.to_owned(),
+            msg,
+        };
+        let mut r = BufReader::new(File::open(path).map_err(|err| CheckpointError::Io {
+            path: path.to_owned(),
+            err,
+        })?);
+
+        let mut magic = [0; 8];
+        r.read_exact(&mut magic)
+            .map_err(|err| corrupt(err.to_string()))?;
+        if &magic != MAGIC {
+            return Err(corrupt("unrecognized header".into()));
+        }
+        let checkpoint: Checkpoint =
+            bincode::deserialize_from(r).map_err(|err| corrupt(err.to_string()))?;
+
+        let pixels = checkpoint.width as usize * checkpoint.height as usize;
+        if checkpoint.radiance.len() != pixels
+            || checkpoint.luminance_sq.len() != pixels
+            || checkpoint.samples.len() != pixels
+            || checkpoint.filtered.len() != pixels
+            || checkpoint.weights.len() != pixels
+            || checkpoint.aov_sums.len() != checkpoint.aovs.len()
+            || checkpoint.aov_sums.iter().any(|sums| sums.len() != pixels)
+        {
+            return Err(corrupt("buffer sizes don't match the image size".into()));
+        }
+        Ok(checkpoint)
+    }
+
+    // The most samples any pixel has received.
+    pub fn samples(&self) -> u32 {
+        self.samples.iter().copied().max().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checkpoint() -> Checkpoint {
+        let pixels = 6;
+        Checkpoint {
+            width: 3,
+            height: 2,
+            max_depth: 50,
+            sampler: SamplerKind::Sobol,
+            pattern_samples: 64,
+            seed: 7,
+            aovs: vec![Aov::Albedo],
+            radiance: (0..pixels).map(|i| Vec3::new(i as f32, 0.5, -1.)).collect(),
+            luminance_sq: (0..pixels).map(|i| i as f32 * 0.25).collect(),
+            samples: (0..pixels).map(|i| i as u32).collect(),
+            filter: Filter::default(),
+            filtered: (0..pixels)
+                .map(|i| [i as i64, -(i as i64), 1 << 40])
+                .collect(),
+            weights: (0..pixels).map(|i| i as i64 * 3).collect(),
+            aov_sums: vec![vec![Vec3::new(0.1, 0.2, 0.3); pixels]],
+        }
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("{}-{}.ckpt", name, std::process::id()))
+    }
+
+    #[test]
+    fn round_trip() {
+        let path = temp_path("round-trip");
+        let saved = checkpoint();
+        saved.save(&path).unwrap();
+        let loaded = Checkpoint::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            bincode::serialize(&saved).unwrap(),
+            bincode::serialize(&loaded).unwrap()
+        );
+        assert_eq!(loaded.samples(), 5);
+    }
+
+    #[test]
+    fn other_headers_are_rejected() {
+        let path = temp_path("header");
+        checkpoint().save(&path).unwrap();
+        let mut bytes = fs::read(&path).unwrap();
+
+        // An older version of the format, and something else entirely.
+        for magic in [b"RTCKPT04", b"\x89PNG\r\n\x1a\n"] {
+            bytes[..8].copy_from_slice(magic);
+            fs::write(&path, &bytes).unwrap();
+            match Checkpoint::load(&path) {
+                Err(CheckpointError::Corrupt { msg, .. }) => assert_eq!(msg, "unrecognized header"),
+                Err(err) => panic!("unexpected error: {}", err),
+                Ok(_) => panic!("loaded"),
+            }
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn truncated_files_are_rejected() {
+        let path = temp_path("truncated");
+        checkpoint().save(&path).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        for len in [4, 40, bytes.len() - 1] {
+            fs::write(&path, &bytes[..len]).unwrap();
+            assert!(matches!(
+                Checkpoint::load(&path),
+                Err(CheckpointError::Corrupt { .. })
+            ));
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn buffers_must_cover_the_image() {
+        let path = temp_path("size");
+        let mut wrong = checkpoint();
+        wrong.width = 4;
+        wrong.save(&path).unwrap();
+        let result = Checkpoint::load(&path);
+        fs::remove_file(&path).unwrap();
+        match result {
+            Err(CheckpointError::Corrupt { msg, .. }) => {
+                assert_eq!(msg, "buffer sizes don't match the image size")
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("loaded"),
+        }
+    }
+}
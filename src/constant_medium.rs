@@ -0,0 +1,83 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::{hash_to_float, mix};
+use crate::vec3::Vec3;
+
+// A hash of everything about r. hit isn't given a sampler, so volumes draw
+// their random numbers from the ray instead: no two rays of a render are
+// alike, and asking about the same ray twice, as the BVH may, gets the same
+// answer.
+pub fn ray_hash(r: &Ray) -> u64 {
+    let (o, d) = (r.origin(), r.direction());
+    let mut h = mix(r.time().to_bits() as u64);
+    for c in 0..3 {
+        h = mix(h ^ o[c].to_bits() as u64);
+        h = mix(h ^ ((d[c].to_bits() as u64) << 32));
+    }
+    h
+}
+
+// A volume of uniform density filling a convex boundary, such as a sphere or
+// a box. Rays travelling through it scatter at exponentially distributed
+// distances, in directions given by the phase function, which is normally
+// Material::Isotropic.
+pub struct ConstantMedium {
+    boundary: Box<dyn Hittable>,
+    neg_inv_density: f32,
+    phase_function: Material,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: impl 'static + Hittable,
+        density: f32,
+        phase_function: Material,
+    ) -> ConstantMedium {
+        ConstantMedium {
+            boundary: Box::new(boundary),
+            neg_inv_density: -1. / density,
+            phase_function,
+        }
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        // Where the ray's line enters and leaves the boundary, which may be
+        // behind its origin if it starts inside.
+        let enter = self.boundary.hit(r, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.hit(r, enter.t + 0.0001, f32::INFINITY)?;
+        let t0 = enter.t.max(t_min);
+        let t1 = exit.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+
+        let length = r.direction().length();
+        let distance_inside = (t1 - t0) * length;
+        // 1 - x is in (0, 1], so the logarithm is finite.
+        let hit_distance = self.neg_inv_density * (1. - hash_to_float(ray_hash(r))).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t0 + hit_distance / length;
+        Some(HitRecord {
+            t,
+            p: r.point_at_parameter(t),
+            // Arbitrary; the phase function doesn't use it.
+            normal: Vec3::new(1., 0., 0.),
+            mat: &self.phase_function,
+            u: 0.,
+            v: 0.,
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
@@ -3,6 +3,7 @@ use crate::hittable::{flip_normals, HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
 use crate::rectangle::{XYRect, XZRect, YZRect};
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct CornellBox {
@@ -72,11 +73,23 @@ impl CornellBox {
 }
 
 impl Hittable for CornellBox {
-    fn hit<'a>(&self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
+    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord<'a>> {
         self.sides.hit(r, t0, t1)
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         Some(AABB::new(self.pmin, self.pmax))
     }
+
+    fn is_emissive(&self) -> bool {
+        self.sides.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        self.sides.pdf_value(o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        self.sides.random(o, time, sampler)
+    }
 }
@@ -0,0 +1,144 @@
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::aov::Aov;
+use crate::framebuffer::Framebuffer;
+use crate::vec3::Vec3;
+
+// B3 spline weights, applied at increasing spacing on every pass.
+const KERNEL: [f32; 5] = [1. / 16., 1. / 4., 3. / 8., 1. / 4., 1. / 16.];
+
+// Edge-avoiding a-trous wavelet filter (Dammertz et al. 2010). Each pass
+// blurs with a 5x5 kernel whose taps are twice as far apart as in the
+// previous pass, and every tap is weighted down where the color, albedo,
+// normal or depth differ from the center pixel, so the blur stays within
+// surfaces.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Denoiser {
+    pub iterations: u32,
+    // Tolerance for differences in tone mapped color. It is halved after
+    // every pass, as the noise left to remove shrinks.
+    pub sigma_color: f32,
+    pub sigma_albedo: f32,
+    // Exponent for the cosine between normals.
+    pub sigma_normal: f32,
+    // Tolerance for depth differences, relative to the depth of the center.
+    pub sigma_depth: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 1.,
+            sigma_albedo: 0.1,
+            sigma_normal: 64.,
+            sigma_depth: 0.05,
+        }
+    }
+}
+
+// Compresses radiance so that color differences in bright and dark regions
+// count alike.
+fn compress(c: Vec3) -> Vec3 {
+    Vec3::new(c[0] / (1. + c[0]), c[1] / (1. + c[1]), c[2] / (1. + c[2]))
+}
+
+impl Denoiser {
+    // The AOVs used to guide the filter.
+    pub const GUIDES: [Aov; 3] = [Aov::Albedo, Aov::Normal, Aov::Depth];
+
+    // Filters the radiance of fb in place, guided by whichever of the GUIDES
+    // layers it has. Radiance is divided by the albedo before filtering and
+    // multiplied back afterwards, which keeps texture detail sharp.
+    pub fn denoise(&self, fb: &mut Framebuffer) {
+        let (w, h) = (fb.width() as usize, fb.height() as usize);
+        let albedo = fb.aov(Aov::Albedo);
+        let normal = fb.aov(Aov::Normal);
+        let depth = fb.aov(Aov::Depth);
+
+        let demodulate = |c: Vec3, a: Vec3| {
+            let divide = |c: f32, a: f32| if a > 1e-3 { c / a } else { c };
+            Vec3::new(divide(c[0], a[0]), divide(c[1], a[1]), divide(c[2], a[2]))
+        };
+        let mut current: Vec<Vec3> = match albedo {
+            Some(albedo) => fb
+                .pixels()
+                .iter()
+                .zip(albedo)
+                .map(|(&c, &a)| demodulate(c, a))
+                .collect(),
+            None => fb.pixels().to_vec(),
+        };
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let sigma_color = self.sigma_color / (1 << iteration) as f32;
+            let mut next = vec![Vec3::default(); w * h];
+            next.par_chunks_mut(w).enumerate().for_each(|(y, row)| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    let p = y * w + x;
+                    let color_p = compress(current[p]);
+                    let mut sum = Vec3::default();
+                    let mut total = 0.;
+
+                    for (dy, ky) in KERNEL.iter().enumerate() {
+                        let qy = y as isize + (dy as isize - 2) * step;
+                        if qy < 0 || qy >= h as isize {
+                            continue;
+                        }
+                        for (dx, kx) in KERNEL.iter().enumerate() {
+                            let qx = x as isize + (dx as isize - 2) * step;
+                            if qx < 0 || qx >= w as isize {
+                                continue;
+                            }
+                            let q = qy as usize * w + qx as usize;
+
+                            let mut weight = kx * ky;
+                            let d = compress(current[q]) - color_p;
+                            weight *= (-d.squared_length() / (sigma_color * sigma_color)).exp();
+                            if let Some(albedo) = albedo {
+                                let d = albedo[q] - albedo[p];
+                                weight *= (-d.squared_length()
+                                    / (self.sigma_albedo * self.sigma_albedo))
+                                    .exp();
+                            }
+                            if let Some(normal) = normal {
+                                let cosine = normal[p].dot(normal[q]).max(0.);
+                                weight *= cosine.powf(self.sigma_normal);
+                            }
+                            if let Some(depth) = depth {
+                                let z = depth[p][0];
+                                let d = (depth[q][0] - z) / (self.sigma_depth * z.max(1e-3));
+                                weight *= (-d * d).exp();
+                            }
+
+                            sum += current[q] * weight;
+                            total += weight;
+                        }
+                    }
+                    // The center tap always has a positive weight unless its
+                    // own features are degenerate, in which case keep it.
+                    *out = if total > 0. { sum / total } else { current[p] };
+                }
+            });
+            current = next;
+        }
+
+        if let Some(albedo) = albedo {
+            let remodulate = |c: Vec3, a: Vec3| {
+                let multiply = |c: f32, a: f32| if a > 1e-3 { c * a } else { c };
+                Vec3::new(
+                    multiply(c[0], a[0]),
+                    multiply(c[1], a[1]),
+                    multiply(c[2], a[2]),
+                )
+            };
+            for (c, &a) in current.iter_mut().zip(albedo) {
+                *c = remodulate(*c, a);
+            }
+        }
+        fb.pixels_mut().copy_from_slice(&current);
+    }
+}
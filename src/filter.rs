@@ -0,0 +1,133 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FilterKind {
+    Box,
+    Tent,
+    Gaussian,
+    // Mitchell-Netravali with B = C = 1/3.
+    Mitchell,
+    // A sinc windowed by a sinc stretched to the radius.
+    Lanczos,
+}
+
+impl FilterKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            FilterKind::Box => "box",
+            FilterKind::Tent => "tent",
+            FilterKind::Gaussian => "gaussian",
+            FilterKind::Mitchell => "mitchell",
+            FilterKind::Lanczos => "lanczos",
+        }
+    }
+
+    pub fn default_radius(self) -> f32 {
+        match self {
+            FilterKind::Box => 0.5,
+            FilterKind::Tent => 1.,
+            FilterKind::Gaussian => 1.5,
+            FilterKind::Mitchell => 2.,
+            FilterKind::Lanczos => 2.,
+        }
+    }
+}
+
+// Pixel reconstruction filter: every sample counts towards each pixel whose
+// center is within radius of it on both axes, weighted by the filter at the
+// offset between them. The default, a box of radius 0.5, averages the
+// samples within each pixel.
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Filter {
+    pub kind: FilterKind,
+    // In pixels; None for the kind's default radius.
+    pub radius: Option<f32>,
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter {
+            kind: FilterKind::Box,
+            radius: None,
+        }
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    let x = std::f32::consts::PI * x;
+    if x.abs() < 1e-5 {
+        1.
+    } else {
+        x.sin() / x
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f32 {
+        self.radius.unwrap_or_else(|| self.kind.default_radius())
+    }
+
+    // The weight for a sample at offset (dx, dy) from a pixel center. Mitchell
+    // and Lanczos have negative lobes.
+    pub fn weight(&self, dx: f32, dy: f32) -> f32 {
+        self.evaluate(dx) * self.evaluate(dy)
+    }
+
+    fn evaluate(&self, d: f32) -> f32 {
+        let radius = self.radius();
+        let d = d.abs();
+        if d > radius {
+            return 0.;
+        }
+        match self.kind {
+            FilterKind::Box => 1.,
+            FilterKind::Tent => radius - d,
+            FilterKind::Gaussian => {
+                // Shifted down to reach zero at the radius.
+                let sigma = radius / 3.;
+                let gaussian = |x: f32| (-x * x / (2. * sigma * sigma)).exp();
+                (gaussian(d) - gaussian(radius)).max(0.)
+            }
+            FilterKind::Mitchell => {
+                let (b, c) = (1. / 3., 1. / 3.);
+                let x = 2. * d / radius;
+                if x < 1. {
+                    ((12. - 9. * b - 6. * c) * x * x * x
+                        + (-18. + 12. * b + 6. * c) * x * x
+                        + (6. - 2. * b))
+                        / 6.
+                } else {
+                    ((-b - 6. * c) * x * x * x
+                        + (6. * b + 30. * c) * x * x
+                        + (-12. * b - 48. * c) * x
+                        + (8. * b + 24. * c))
+                        / 6.
+                }
+            }
+            FilterKind::Lanczos => sinc(d) * sinc(d / radius),
+        }
+    }
+
+    // The pixels along one axis whose centers are within the radius of a
+    // sample at offset in [0, 1) within pixel, each with the distance from
+    // its center to the sample. The extent is half-open so that a box of
+    // radius 0.5 covers exactly one pixel.
+    pub fn footprint(
+        &self,
+        pixel: u32,
+        offset: f32,
+        len: u32,
+    ) -> impl Iterator<Item = (usize, f32)> {
+        // The bounds are found in f64, where they can't be rounded across an
+        // integer.
+        let radius = self.radius() as f64;
+        let d = offset - 0.5;
+        let first = (d as f64 - radius).floor() as i64 + 1;
+        let last = (d as f64 + radius).floor() as i64;
+        let pixel = pixel as i64;
+        (first.max(-pixel)..=last.min(len as i64 - 1 - pixel))
+            .map(move |k| ((pixel + k) as usize, d - k as f32))
+    }
+}
@@ -0,0 +1,314 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use exr::prelude::{AnyChannel, AnyChannels, Encoding, FlatSamples, Image, Layer, WritableImage};
+use image::hdr::HDREncoder;
+use image::{Rgb, RgbImage};
+
+use crate::aov::Aov;
+use crate::tonemap::ToneMapper;
+use crate::vec3::Vec3;
+
+// Linear radiance for every pixel of a rendered image, stored row by row with
+// the top row first, along with any AOV layers in the same layout.
+#[derive(Clone, Debug)]
+pub struct Framebuffer {
+    width: u32,
+    height: u32,
+    pixels: Vec<Vec3>,
+    aovs: Vec<(Aov, Vec<Vec3>)>,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32) -> Framebuffer {
+        Framebuffer {
+            width,
+            height,
+            pixels: vec![Vec3::default(); (width * height) as usize],
+            aovs: Vec::new(),
+        }
+    }
+
+    // Adds a zeroed layer for aov, if there isn't one already.
+    pub fn add_aov(&mut self, aov: Aov) {
+        if self.aov(aov).is_none() {
+            let layer = vec![Vec3::default(); self.pixels.len()];
+            self.aovs.push((aov, layer));
+        }
+    }
+
+    pub fn remove_aov(&mut self, aov: Aov) {
+        self.aovs.retain(|(a, _)| *a != aov);
+    }
+
+    // The AOVs with layers, in the order they were added.
+    pub fn aovs(&self) -> Vec<Aov> {
+        self.aovs.iter().map(|(aov, _)| *aov).collect()
+    }
+
+    pub fn aov(&self, aov: Aov) -> Option<&[Vec3]> {
+        self.aovs
+            .iter()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, layer)| layer.as_slice())
+    }
+
+    pub fn aov_mut(&mut self, aov: Aov) -> Option<&mut [Vec3]> {
+        self.aovs
+            .iter_mut()
+            .find(|(a, _)| *a == aov)
+            .map(|(_, layer)| layer.as_mut_slice())
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Vec3] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [Vec3] {
+        &mut self.pixels
+    }
+
+    pub fn get(&self, x: u32, y: u32) -> Vec3 {
+        self.pixels[(y * self.width + x) as usize]
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, color: Vec3) {
+        self.pixels[(y * self.width + x) as usize] = color;
+    }
+
+    pub fn to_rgb8(&self, tonemap: &ToneMapper) -> RgbImage {
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            Rgb(tonemap.to_rgb8(self.get(x, y)))
+        })
+    }
+
+    // Writes the image in the format given by the file extension. exr, pfm
+    // and hdr files keep the linear radiance as floats; anything else is tone
+    // mapped and quantized to 8 bits. Only exr files include the AOVs.
+    pub fn save<P: AsRef<Path>>(&self, path: P, tonemap: &ToneMapper) -> io::Result<()> {
+        let path = path.as_ref();
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("exr") => self.save_exr(path),
+            Some("pfm") => self.save_pfm(path),
+            Some("hdr") => self.save_hdr(path),
+            _ => self.to_rgb8(tonemap).save(path),
+        }
+    }
+
+    // Writes a single AOV layer as an image of its own. Float formats get the
+    // raw values and anything else a visualization of them.
+    pub fn save_aov<P: AsRef<Path>>(&self, aov: Aov, path: P) -> io::Result<()> {
+        let path = path.as_ref();
+        let layer = match self.aov(aov) {
+            Some(layer) => layer,
+            None => {
+                return Err(io::Error::new(
+                    io::ErrorKind::NotFound,
+                    format!("no {} layer was rendered", aov.name()),
+                ))
+            }
+        };
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        if let Some("exr" | "pfm" | "hdr") = extension.as_deref() {
+            let fb = Framebuffer {
+                width: self.width,
+                height: self.height,
+                pixels: layer.to_vec(),
+                aovs: Vec::new(),
+            };
+            return fb.save(path, &ToneMapper::default());
+        }
+
+        let pixels = aov.visualize(layer);
+        RgbImage::from_fn(self.width, self.height, |x, y| {
+            Rgb(pixels[(y * self.width + x) as usize])
+        })
+        .save(path)
+    }
+
+    // OpenEXR with 32-bit float channels: RGB for the radiance and a group of
+    // channels for each AOV.
+    pub fn save_exr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let channel = |name: &str, layer: &[Vec3], c: usize| {
+            let samples = layer.iter().map(|value| value[c]).collect();
+            AnyChannel::new(name, FlatSamples::F32(samples))
+        };
+        let mut channels = vec![
+            channel("R", &self.pixels, 0),
+            channel("G", &self.pixels, 1),
+            channel("B", &self.pixels, 2),
+        ];
+        for (aov, layer) in &self.aovs {
+            for (c, name) in aov.channels().iter().enumerate() {
+                channels.push(channel(name, layer, c));
+            }
+        }
+
+        let size = (self.width as usize, self.height as usize);
+        let layer = Layer::new(
+            size,
+            Default::default(),
+            Encoding::FAST_LOSSLESS,
+            AnyChannels::sort(channels.into()),
+        );
+        Image::from_layer(layer)
+            .write()
+            .to_file(path)
+            .map_err(|err| match err {
+                exr::error::Error::Io(err) => err,
+                err => io::Error::new(io::ErrorKind::InvalidData, err.to_string()),
+            })
+    }
+
+    // Portable float map: a short text header followed by little-endian
+    // floats, with the bottom row first.
+    pub fn save_pfm<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        // A negative scale marks the data as little-endian.
+        write!(w, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for row in self.pixels.chunks(self.width as usize).rev() {
+            for color in row {
+                for c in 0..3 {
+                    w.write_all(&color[c].to_le_bytes())?;
+                }
+            }
+        }
+        w.flush()
+    }
+
+    // Radiance RGBE, which shares an 8-bit exponent between the channels.
+    pub fn save_hdr<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let w = BufWriter::new(File::create(path)?);
+        let data: Vec<_> = self
+            .pixels
+            .iter()
+            .map(|color| Rgb([color[0], color[1], color[2]]))
+            .collect();
+        HDREncoder::new(w).encode(&data, self.width as usize, self.height as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::io::BufReader;
+
+    use image::hdr::HDRDecoder;
+
+    // A 3x2 image with a different color in every pixel.
+    fn framebuffer() -> Framebuffer {
+        let mut fb = Framebuffer::new(3, 2);
+        for y in 0..2 {
+            for x in 0..3 {
+                let i = (y * 3 + x) as f32;
+                fb.set(x, y, Vec3::new(i, 0.25 * i + 0.5, 1e-3 * i));
+            }
+        }
+        fb
+    }
+
+    fn temp_path(test: &str, extension: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!(
+            "framebuffer-{}-{}.{}",
+            test,
+            std::process::id(),
+            extension
+        ))
+    }
+
+    #[test]
+    fn pfm_is_little_endian_bottom_row_first() {
+        let fb = framebuffer();
+        let path = temp_path("pfm", "pfm");
+        fb.save(&path, &ToneMapper::default()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let header = b"PF\n3 2\n-1.0\n";
+        assert_eq!(&bytes[..header.len()], header);
+        let floats: Vec<f32> = bytes[header.len()..]
+            .chunks(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+            .collect();
+        assert_eq!(floats.len(), 3 * 2 * 3);
+        for (i, color) in floats.chunks(3).enumerate() {
+            let (x, y) = (i as u32 % 3, 1 - i as u32 / 3);
+            let expected = fb.get(x, y);
+            assert_eq!(color, &[expected[0], expected[1], expected[2]]);
+        }
+    }
+
+    #[test]
+    fn hdr_has_a_radiance_header_and_round_trips() {
+        let fb = framebuffer();
+        let path = temp_path("hdr", "hdr");
+        fb.save(&path, &ToneMapper::default()).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        let text = String::from_utf8_lossy(&bytes);
+        assert!(text.starts_with("#?RADIANCE\n"));
+        assert!(text.contains("FORMAT=32-bit_rle_rgbe\n"));
+        assert!(text.contains("\n-Y 2 +X 3\n"));
+
+        let decoder = HDRDecoder::new(BufReader::new(File::open(&path).unwrap())).unwrap();
+        let pixels = decoder.read_image_hdr().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(pixels.len(), 6);
+        for (i, Rgb(rgb)) in pixels.iter().enumerate() {
+            let expected = fb.pixels()[i];
+            // The shared exponent leaves 8 bits relative to the largest
+            // channel.
+            let tolerance = expected[0].max(expected[1]) / 128.;
+            for c in 0..3 {
+                assert!((rgb[c] - expected[c]).abs() <= tolerance);
+            }
+        }
+    }
+
+    #[test]
+    fn exr_round_trips_the_radiance_and_aovs() {
+        let mut fb = framebuffer();
+        fb.add_aov(Aov::Depth);
+        for (i, z) in fb.aov_mut(Aov::Depth).unwrap().iter_mut().enumerate() {
+            *z = Vec3::new(10. + i as f32, 0., 0.);
+        }
+        let path = temp_path("exr", "exr");
+        fb.save(&path, &ToneMapper::default()).unwrap();
+        let image = exr::prelude::read_all_flat_layers_from_file(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(image.layer_data.len(), 1);
+        let layer = &image.layer_data[0];
+        assert_eq!(layer.size, exr::prelude::Vec2(3, 2));
+        let channels = &layer.channel_data.list;
+        let names: Vec<String> = channels.iter().map(|c| c.name.to_string()).collect();
+        assert_eq!(names, ["B", "G", "R", "Z"]);
+        let samples = |c: usize| match &channels[c].sample_data {
+            FlatSamples::F32(samples) => samples.clone(),
+            _ => panic!("{} isn't f32", names[c]),
+        };
+        let depth = fb.aov(Aov::Depth).unwrap();
+        for (i, (expected, z)) in fb.pixels().iter().zip(depth).enumerate() {
+            assert_eq!(samples(2)[i], expected[0]);
+            assert_eq!(samples(1)[i], expected[1]);
+            assert_eq!(samples(0)[i], expected[2]);
+            assert_eq!(samples(3)[i], z[0]);
+        }
+    }
+}
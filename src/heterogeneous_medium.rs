@@ -0,0 +1,203 @@
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::aabb::AABB;
+use crate::constant_medium::ray_hash;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::perlin;
+use crate::ray::Ray;
+use crate::texture::Texture;
+use crate::tonemap::saturate;
+use crate::vec3::Vec3;
+use crate::voxel_grid::VoxelGrid;
+
+// Where a heterogeneous medium is dense, as a fraction of its full density.
+pub enum DensityField {
+    // Perlin turbulence at the given spatial frequency.
+    Noise { frequency: f32 },
+    Grid(VoxelGrid),
+}
+
+impl DensityField {
+    pub fn value(&self, p: Vec3) -> f32 {
+        match self {
+            DensityField::Noise { frequency } => saturate(perlin::turbulence(*frequency * p, 7)),
+            DensityField::Grid(grid) => grid.value(p),
+        }
+    }
+}
+
+// A volume filling a convex boundary whose density varies from point to point
+// according to field. Absorption and scattering give the coefficients of the
+// medium per channel where it is at full density.
+//
+// Paths are sampled by spectral tracking: tentative collisions are placed at
+// the rate of the largest extinction at full density, which bounds every
+// channel everywhere, and each one is made either a real scattering or a null
+// collision that the ray passes through. Each channel of the path is then
+// weighted by its own coefficient over the chance of the event that was
+// picked, so every channel gets its own transmittance and albedo however
+// they differ. Absorption is never sampled; it only lowers the weights.
+pub struct HeterogeneousMedium {
+    boundary: Box<dyn Hittable>,
+    field: DensityField,
+    absorption: Vec3,
+    scattering: Vec3,
+    // Extinction of the densest channel at full density, the majorant.
+    max_extinction: f32,
+    // Real collisions scatter with this, a white isotropic phase function, as
+    // their weight already holds the albedo.
+    phase_function: Material,
+    null: Material,
+}
+
+impl HeterogeneousMedium {
+    pub fn new(
+        boundary: impl 'static + Hittable,
+        field: DensityField,
+        absorption: Vec3,
+        scattering: Vec3,
+    ) -> HeterogeneousMedium {
+        let absorption = max(absorption, Vec3::default());
+        let scattering = max(scattering, Vec3::default());
+        let extinction = absorption + scattering;
+        HeterogeneousMedium {
+            boundary: Box::new(boundary),
+            field,
+            absorption,
+            scattering,
+            max_extinction: max_channel(extinction),
+            phase_function: Material::Isotropic(Texture::solid((1., 1., 1.))),
+            null: Material::Null,
+        }
+    }
+}
+
+fn max(a: Vec3, b: Vec3) -> Vec3 {
+    Vec3::new(a[0].max(b[0]), a[1].max(b[1]), a[2].max(b[2]))
+}
+
+fn max_channel(v: Vec3) -> f32 {
+    v[0].max(v[1]).max(v[2])
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        if self.max_extinction <= 0. {
+            return None;
+        }
+        let enter = self.boundary.hit(r, f32::NEG_INFINITY, f32::INFINITY)?;
+        let exit = self.boundary.hit(r, enter.t + 0.0001, f32::INFINITY)?;
+        let t0 = enter.t.max(t_min);
+        let t1 = exit.t.min(t_max);
+        if t0 >= t1 {
+            return None;
+        }
+
+        // Only the first tentative collision is found here. The renderer
+        // carries on past null ones with a new ray, and a surface closer than
+        // the collision hides it, which is right as free paths are
+        // memoryless.
+        let mut rng = Pcg32::seed_from_u64(ray_hash(r));
+        let rate = self.max_extinction * r.direction().length();
+        let t = t0 - (1. - rng.gen::<f32>()).ln() / rate;
+        if t >= t1 {
+            return None;
+        }
+        let p = r.point_at_parameter(t);
+        let density = self.field.value(p);
+        let scattering = density * self.scattering;
+        let null = Vec3::new(
+            self.max_extinction,
+            self.max_extinction,
+            self.max_extinction,
+        ) - density * (self.absorption + self.scattering);
+        let null = max(null, Vec3::default());
+
+        // Events are picked in proportion to the largest channel of each, so
+        // that no channel's weight can grow without bound.
+        let (p_scatter, p_null) = (max_channel(scattering), max_channel(null));
+        let total = p_scatter + p_null;
+        let (mat, weight) = if total <= 0. {
+            // Fully absorbing here; the path ends.
+            (&self.null, Vec3::default())
+        } else if rng.gen::<f32>() * total < p_scatter {
+            (
+                &self.phase_function,
+                scattering * (total / (self.max_extinction * p_scatter)),
+            )
+        } else {
+            (&self.null, null * (total / (self.max_extinction * p_null)))
+        };
+        Some(HitRecord {
+            t,
+            p,
+            normal: Vec3::new(1., 0., 0.),
+            mat,
+            u: 0.,
+            v: 0.,
+            id: 0,
+            weight,
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.boundary.bounding_box(t0, t1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cornellbox::CornellBox;
+
+    // Follows r through the medium as a shadow ray would, multiplying in the
+    // weights of null collisions until it leaves or really collides.
+    fn transmittance(medium: &HeterogeneousMedium, r: Ray) -> Vec3 {
+        let mut ray = r;
+        let mut weight = Vec3::new(1., 1., 1.);
+        while let Some(rec) = medium.hit(&ray, 0., f32::MAX) {
+            if !rec.mat.is_null() {
+                return Vec3::default();
+            }
+            weight *= rec.weight;
+            ray = Ray::new(rec.p, ray.direction(), ray.time());
+        }
+        weight
+    }
+
+    #[test]
+    fn chromatic_transmittance_matches_beer_lambert() {
+        let (min, max) = (Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+        let boundary = CornellBox::new(min, max, Material::Null);
+        let field = DensityField::Grid(VoxelGrid::new([1, 1, 1], vec![1.], min, max));
+        let absorption = Vec3::new(0.2, 0.5, 1.5);
+        let scattering = Vec3::new(0.3, 0.1, 0.5);
+        let medium = HeterogeneousMedium::new(boundary, field, absorption, scattering);
+
+        let n = 200;
+        let mut sum = Vec3::default();
+        for i in 0..n {
+            for j in 0..n {
+                let y = 0.1 + 0.8 * (i as f32 + 0.5) / n as f32;
+                let z = 0.1 + 0.8 * (j as f32 + 0.5) / n as f32;
+                let r = Ray::new(Vec3::new(-1., y, z), Vec3::new(1., 0., 0.), 0.);
+                sum += transmittance(&medium, r);
+            }
+        }
+        let estimate = sum / (n * n) as f32;
+        let extinction = absorption + scattering;
+        for c in 0..3 {
+            // The medium is one unit across.
+            let expected = (-extinction[c]).exp();
+            assert!(
+                (estimate[c] - expected).abs() < 0.01,
+                "channel {}: {} vs {}",
+                c,
+                estimate[c],
+                expected
+            );
+        }
+    }
+}
@@ -1,6 +1,9 @@
+use std::sync::Arc;
+
 use crate::aabb::{surrounding_box, AABB};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct HitRecord<'a> {
@@ -10,22 +13,93 @@ pub struct HitRecord<'a> {
     pub mat: &'a Material,
     pub u: f32,
     pub v: f32,
+    // Index of the object that was hit in the scene's list of objects, so
+    // that objects can be told apart in the id AOV.
+    pub id: u32,
+    // Factor the throughput of a path is multiplied by on arriving here,
+    // which is 1 except at collisions sampled in heterogeneous media.
+    pub weight: Vec3,
 }
 
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>>;
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB>;
+
+    // Light sampling. Emissive hittables can be sampled directly: random picks
+    // a direction from o towards the surface and pdf_value gives the solid
+    // angle density of sampling v from o.
+    fn is_emissive(&self) -> bool {
+        false
+    }
+
+    fn pdf_value(&self, _o: Vec3, _v: Vec3, _time: f32) -> f32 {
+        0.
+    }
+
+    fn random(&self, _o: Vec3, _time: f32, _sampler: &mut dyn Sampler) -> Vec3 {
+        Vec3::new(1., 0., 0.)
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Box<T> {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        (**self).pdf_value(o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(o, time, sampler)
+    }
+}
+
+impl<T: Hittable + ?Sized> Hittable for Arc<T> {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        (**self).hit(r, t_min, t_max)
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        (**self).bounding_box(t0, t1)
+    }
+
+    fn is_emissive(&self) -> bool {
+        (**self).is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        (**self).pdf_value(o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        (**self).random(o, time, sampler)
+    }
 }
 
 impl Hittable for Vec<Box<dyn Hittable>> {
     fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
         self.iter()
-            .filter_map(|item| item.hit(r, t_min, t_max))
+            .enumerate()
+            .filter_map(|(i, item)| {
+                item.hit(r, t_min, t_max).map(|rec| HitRecord {
+                    id: i as u32,
+                    ..rec
+                })
+            })
             .min_by(|r1, r2| r1.t.partial_cmp(&r2.t).unwrap())
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
-        if let Some(init) = self.get(0).and_then(|x| x.bounding_box(t0, t1)) {
+        if let Some(init) = self.first().and_then(|x| x.bounding_box(t0, t1)) {
             self.iter().try_fold(init, |box1, item| {
                 item.bounding_box(t0, t1)
                     .map(|box2| surrounding_box(box1, box2))
@@ -34,25 +108,59 @@ impl Hittable for Vec<Box<dyn Hittable>> {
             None
         }
     }
+
+    fn is_emissive(&self) -> bool {
+        self.iter().any(|item| item.is_emissive())
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let lights = self.iter().filter(|item| item.is_emissive());
+        let (sum, count) = lights.fold((0., 0), |(sum, count), item| {
+            (sum + item.pdf_value(o, v, time), count + 1)
+        });
+        if count > 0 {
+            sum / count as f32
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let mut lights = self.iter().filter(|item| item.is_emissive());
+        let count = lights.clone().count();
+        let i = (sampler.get_1d() * count as f32) as usize;
+        match lights.nth(i.min(count.saturating_sub(1))) {
+            Some(item) => item.random(o, time, sampler),
+            None => Vec3::new(1., 0., 0.),
+        }
+    }
 }
 
 pub struct FlipNormals(Box<dyn Hittable>);
 
 impl Hittable for FlipNormals {
     fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
-        if let Some(rec) = self.0.hit(r, t_min, t_max) {
-            Some(HitRecord {
-                normal: -rec.normal,
-                ..rec
-            })
-        } else {
-            None
-        }
+        self.0.hit(r, t_min, t_max).map(|rec| HitRecord {
+            normal: -rec.normal,
+            ..rec
+        })
     }
 
     fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
         self.0.bounding_box(t0, t1)
     }
+
+    fn is_emissive(&self) -> bool {
+        self.0.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        self.0.pdf_value(o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        self.0.random(o, time, sampler)
+    }
 }
 
 pub fn flip_normals<T: 'static + Hittable>(hittable: T) -> Box<FlipNormals> {
@@ -0,0 +1,167 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::transform::{hit_transformed, pdf_value_transformed, random_transformed, transform_box};
+use crate::vec3::Vec3;
+
+// One placement of geometry that may be shared by any number of instances,
+// such as a BVH of a whole model, so that it is only held in memory once.
+// Each instance has its own matrix and can draw everything with its own
+// material instead of the geometry's.
+pub struct Instance {
+    geometry: Arc<dyn Hittable>,
+    matrix: Mat4,
+    inverse: Mat4,
+    normal_matrix: Mat4,
+    material: Option<Material>,
+}
+
+impl Instance {
+    // Panics if matrix isn't invertible.
+    pub fn new(geometry: Arc<dyn Hittable>, matrix: Mat4, material: Option<Material>) -> Instance {
+        let inverse = matrix.inverse().expect("instance matrix is not invertible");
+        Instance {
+            geometry,
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+            material,
+        }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        let rec = hit_transformed(
+            &*self.geometry,
+            &self.matrix,
+            &self.inverse,
+            &self.normal_matrix,
+            r,
+            t_min,
+            t_max,
+        )?;
+        Some(match &self.material {
+            Some(mat) => HitRecord { mat, ..rec },
+            None => rec,
+        })
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.geometry
+            .bounding_box(t0, t1)
+            .map(|bbox| transform_box(&self.matrix, &bbox))
+    }
+
+    // Light sampling goes through the geometry, which only knows which of its
+    // parts are lights by their own materials. So an instance is sampled when
+    // those parts are and the override, if any, is a light too; any other
+    // light an override makes is still found, just by BSDF sampling alone.
+    fn is_emissive(&self) -> bool {
+        let override_emits = self.material.as_ref().is_none_or(Material::is_light);
+        override_emits && self.geometry.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        pdf_value_transformed(&*self.geometry, &self.inverse, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        random_transformed(
+            &*self.geometry,
+            &self.matrix,
+            &self.inverse,
+            o,
+            time,
+            sampler,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+
+    fn diffuse() -> Material {
+        Material::Diffuse(Texture::solid((0.5, 0.5, 0.5)))
+    }
+
+    fn light() -> Material {
+        Material::Light(Texture::solid((4., 4., 4.)))
+    }
+
+    fn ball(mat: Material) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Vec3::new(0., 0., 0.), 1., mat))
+    }
+
+    // A ray straight down onto the point (x, 0, z).
+    fn down(x: f32, z: f32) -> Ray {
+        Ray::new(Vec3::new(x, 10., z), Vec3::new(0., -1., 0.), 0.)
+    }
+
+    #[test]
+    fn override_replaces_the_geometrys_material() {
+        let geometry = ball(diffuse());
+        let own = Instance::new(geometry.clone(), Mat4::identity(), None);
+        let metal = Instance::new(
+            geometry,
+            Mat4::identity(),
+            Some(Material::Metal(Vec3::new(1., 1., 1.), 0.)),
+        );
+
+        let rec = own.hit(&down(0., 0.), 0.001, f32::MAX).unwrap();
+        assert!(matches!(rec.mat, Material::Diffuse(_)));
+        let rec = metal.hit(&down(0., 0.), 0.001, f32::MAX).unwrap();
+        assert!(matches!(rec.mat, Material::Metal(..)));
+    }
+
+    #[test]
+    fn instances_share_and_place_their_geometry() {
+        let geometry = ball(diffuse());
+        let near = Instance::new(geometry.clone(), Mat4::translate((5., 0., 0.)), None);
+        let far = Instance::new(
+            geometry.clone(),
+            Mat4::translate((-5., 0., 2.)) * Mat4::scale((2., 3., 2.)),
+            None,
+        );
+        assert_eq!(Arc::strong_count(&geometry), 3);
+
+        let rec = near.hit(&down(5., 0.), 0.001, f32::MAX).unwrap();
+        assert!((rec.p - Vec3::new(5., 1., 0.)).length() < 1e-4);
+        assert!((rec.normal - Vec3::new(0., 1., 0.)).length() < 1e-4);
+        let rec = far.hit(&down(-5., 2.), 0.001, f32::MAX).unwrap();
+        assert!((rec.p - Vec3::new(-5., 3., 2.)).length() < 1e-4);
+        assert!((rec.t - 7.).abs() < 1e-4);
+
+        // Each misses where only the other is.
+        assert!(near.hit(&down(-5., 2.), 0.001, f32::MAX).is_none());
+        assert!(far.hit(&down(5., 0.), 0.001, f32::MAX).is_none());
+
+        let bbox = far.bounding_box(0., 1.).unwrap();
+        assert!((bbox.min - Vec3::new(-7., -3., 0.)).length() < 1e-4);
+        assert!((bbox.max - Vec3::new(-3., 3., 4.)).length() < 1e-4);
+    }
+
+    #[test]
+    fn emissive_only_when_the_geometry_and_any_override_are_lights() {
+        let cases = [
+            (light(), None, true),
+            (light(), Some(light()), true),
+            (light(), Some(diffuse()), false),
+            // Found by BSDF sampling alone, as the geometry can't sample it.
+            (diffuse(), Some(light()), false),
+            (diffuse(), None, false),
+        ];
+        for (mat, material, emissive) in cases {
+            let instance = Instance::new(ball(mat), Mat4::identity(), material);
+            assert_eq!(instance.is_emissive(), emissive);
+        }
+    }
+}
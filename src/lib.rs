@@ -0,0 +1,60 @@
+//! A path tracer. Scenes are built in code from the shapes and materials
+//! below, loaded from TOML files with `Scene::load`, or taken from the
+//! built-in set in `scenes`; `render` turns one into a `Framebuffer` that can
+//! be saved as an image.
+
+#![allow(clippy::upper_case_acronyms)]
+
+pub mod aabb;
+pub mod animated_transform;
+pub mod aov;
+pub mod bvh;
+pub mod camera;
+pub mod checkpoint;
+pub mod constant_medium;
+pub mod cornellbox;
+pub mod denoise;
+pub mod filter;
+pub mod framebuffer;
+pub mod heterogeneous_medium;
+pub mod hittable;
+pub mod instance;
+pub mod mat4;
+pub mod material;
+pub mod moving_sphere;
+pub mod obj;
+pub mod perlin;
+pub mod quaternion;
+pub mod ray;
+pub mod rectangle;
+pub mod render;
+pub mod rotate;
+pub mod sampler;
+pub mod scene;
+pub mod scenes;
+pub mod sphere;
+pub mod texture;
+pub mod tonemap;
+pub mod transform;
+pub mod translate;
+pub mod triangle;
+pub mod vec3;
+pub mod voxel_grid;
+
+pub use aov::Aov;
+pub use bvh::{SplitMethod, BVH};
+pub use camera::{Camera, CameraSettings};
+pub use checkpoint::{Checkpoint, CheckpointError};
+pub use denoise::Denoiser;
+pub use filter::{Filter, FilterKind};
+pub use framebuffer::Framebuffer;
+pub use hittable::{HitRecord, Hittable};
+pub use mat4::Mat4;
+pub use material::Material;
+pub use quaternion::Quat;
+pub use render::{render, render_progressive, Progress, RenderSettings, Renderer};
+pub use sampler::{Sampler, SamplerKind};
+pub use scene::{Scene, SceneError};
+pub use texture::Texture;
+pub use tonemap::{Operator, ToneMapper};
+pub use vec3::Vec3;
@@ -1,265 +1,364 @@
-mod aabb;
-mod bvh;
-mod camera;
-mod cornellbox;
-mod hittable;
-mod material;
-mod moving_sphere;
-mod perlin;
-mod ray;
-mod rectangle;
-mod rotate;
-mod sphere;
-mod texture;
-mod translate;
-mod vec3;
-
-use image::GenericImageView;
-use rand::Rng;
-
-use bvh::BVH;
-use camera::Camera;
-use cornellbox::CornellBox;
-use hittable::{flip_normals, Hittable};
-use material::Material;
-use moving_sphere::MovingSphere;
-use ray::Ray;
-use rectangle::{XYRect, XZRect, YZRect};
-use rotate::RotateY;
-use sphere::Sphere;
-use texture::Texture;
-use translate::Translate;
-use vec3::Vec3;
-
-fn random_scene() -> Vec<Box<dyn Hittable>> {
-    let n = 500;
-    let mut rng = rand::thread_rng();
-    let mut world: Vec<Box<dyn Hittable>> = Vec::with_capacity(n + 1);
-
-    let checker = Texture::checker(
-        Texture::solid((0.2, 0.3, 0.1)),
-        Texture::solid((0.9, 0.9, 0.9)),
-    );
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0., -1000., 0.),
-        1000.,
-        Material::Diffuse(checker),
-    )));
-
-    for a in -11..11 {
-        for b in -11..11 {
-            let center = Vec3::new(
-                a as f32 + 0.9 * rng.gen::<f32>(),
-                0.2,
-                b as f32 + 0.9 * rng.gen::<f32>(),
-            );
-            if (center - Vec3::new(4., 0.2, 0.)).length() <= 0.9 {
-                continue;
-            }
+use std::path::{Path, PathBuf};
+use std::process;
 
-            let choose_mat = rng.gen::<f32>();
-            if choose_mat < 0.8 {
-                world.push(Box::new(MovingSphere::new(
-                    center,
-                    center + Vec3::new(0., 0.5 * rng.gen::<f32>(), 0.),
-                    0.,
-                    1.,
-                    0.2,
-                    Material::Diffuse(Texture::solid(Vec3::new(
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                        rng.gen::<f32>() * rng.gen::<f32>(),
-                    ))),
-                )));
-            } else if choose_mat < 0.95 {
-                world.push(Box::new(Sphere::new(
-                    center,
-                    0.2,
-                    Material::Metal(
-                        Vec3::new(
-                            0.5 * (1. + rng.gen::<f32>()),
-                            0.5 * (1. + rng.gen::<f32>()),
-                            0.5 * (1. + rng.gen::<f32>()),
-                        ),
-                        0.5 * rng.gen::<f32>(),
-                    ),
-                )));
-            } else {
-                world.push(Box::new(Sphere::new(center, 0.2, Material::Glass(1.5))));
-            }
-        }
+use clap::Parser;
+
+use raytracing::scenes::{self, SCENES};
+use raytracing::{
+    Aov, Checkpoint, FilterKind, Framebuffer, Operator, Renderer, SamplerKind, Scene, SplitMethod,
+    ToneMapper, Vec3, BVH,
+};
+
+fn parse_vec3(s: &str) -> Result<Vec3, String> {
+    let parts = s
+        .split(',')
+        .map(|part| part.trim().parse::<f32>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|err| err.to_string())?;
+    match parts[..] {
+        [x, y, z] => Ok(Vec3::new(x, y, z)),
+        _ => Err("expected three comma-separated numbers".into()),
     }
+}
 
-    world.push(Box::new(Sphere::new(
-        Vec3::new(0., 1., 0.),
-        1.,
-        Material::Glass(1.5),
-    )));
-
-    let img = image::open("earthmap.jpg").unwrap();
-    let data = img.raw_pixels();
-    let (w, h) = img.dimensions();
-    world.push(Box::new(Sphere::new(
-        Vec3::new(4., 1., 0.),
-        1.,
-        Material::Diffuse(Texture::Image { data, w, h }),
-    )));
-
-    world.push(Box::new(Sphere::new(
-        Vec3::new(-4., 1., 0.),
-        1.,
-        Material::Metal(Vec3::new(0.7, 0.6, 0.5), 0.),
-    )));
-    world
+fn parse_split_method(s: &str) -> Result<SplitMethod, String> {
+    match s {
+        "median" => Ok(SplitMethod::Median),
+        "sah" => Ok(SplitMethod::Sah),
+        _ => Err("expected `median` or `sah`".into()),
+    }
 }
 
-fn two_spheres() -> Vec<Box<dyn Hittable>> {
-    let checker = Texture::checker(
-        Texture::solid((0.2, 0.3, 0.1)),
-        Texture::solid((0.9, 0.9, 0.9)),
-    );
-    vec![
-        Box::new(Sphere::new(
-            Vec3::new(0., -10., 0.),
-            10.,
-            Material::Diffuse(checker.clone()),
-        )),
-        Box::new(Sphere::new(
-            Vec3::new(0., 10., 0.),
-            10.,
-            Material::Diffuse(checker.clone()),
-        )),
-    ]
+fn parse_operator(s: &str) -> Result<Operator, String> {
+    match s {
+        "clamp" => Ok(Operator::Clamp),
+        "reinhard" => Ok(Operator::Reinhard),
+        "extended_reinhard" => Ok(Operator::ExtendedReinhard),
+        "aces" => Ok(Operator::Aces),
+        "hable" => Ok(Operator::Hable),
+        _ => Err("expected `clamp`, `reinhard`, `extended_reinhard`, `aces` or `hable`".into()),
+    }
 }
 
-fn two_perlin_spheres() -> Vec<Box<dyn Hittable>> {
-    vec![
-        Box::new(Sphere::new(
-            Vec3::new(0., -1000., 0.),
-            1000.,
-            Material::Diffuse(Texture::noise(4.)),
-        )),
-        Box::new(Sphere::new(
-            Vec3::new(0., 2., 0.),
-            2.,
-            Material::Diffuse(Texture::noise(4.)),
-        )),
-    ]
+fn parse_filter(s: &str) -> Result<FilterKind, String> {
+    match s {
+        "box" => Ok(FilterKind::Box),
+        "tent" => Ok(FilterKind::Tent),
+        "gaussian" => Ok(FilterKind::Gaussian),
+        "mitchell" => Ok(FilterKind::Mitchell),
+        "lanczos" => Ok(FilterKind::Lanczos),
+        _ => Err("expected `box`, `tent`, `gaussian`, `mitchell` or `lanczos`".into()),
+    }
 }
 
-fn simple_light() -> Vec<Box<dyn Hittable>> {
-    let pertext = Texture::noise(4.);
-    let solidtext = Texture::solid((4., 4., 4.));
-    vec![
-        Box::new(Sphere::new(
-            Vec3::new(0., -1000., 0.),
-            1000.,
-            Material::Diffuse(pertext.clone()),
-        )),
-        Box::new(Sphere::new(
-            Vec3::new(0., 2., 0.),
-            2.,
-            Material::Diffuse(pertext.clone()),
-        )),
-        Box::new(Sphere::new(
-            Vec3::new(0., 7., 0.),
-            2.,
-            Material::Light(solidtext.clone()),
-        )),
-        Box::new(XYRect::new(
-            3.,
-            5.,
-            1.,
-            3.,
-            -2.,
-            Material::Light(solidtext.clone()),
-        )),
-    ]
+fn parse_sampler(s: &str) -> Result<SamplerKind, String> {
+    match s {
+        "independent" => Ok(SamplerKind::Independent),
+        "stratified" => Ok(SamplerKind::Stratified),
+        "halton" => Ok(SamplerKind::Halton),
+        "sobol" => Ok(SamplerKind::Sobol),
+        _ => Err("expected `independent`, `stratified`, `halton` or `sobol`".into()),
+    }
 }
 
-fn cornell_box() -> Vec<Box<dyn Hittable>> {
-    let red = Material::Diffuse(Texture::solid((0.65, 0.05, 0.05)));
-    let white = Material::Diffuse(Texture::solid((0.73, 0.73, 0.73)));
-    let green = Material::Diffuse(Texture::solid((0.12, 0.45, 0.15)));
-    let light = Material::Light(Texture::solid((15., 15., 15.)));
-
-    vec![
-        flip_normals(YZRect::new(0., 555., 0., 555., 555., green.clone())),
-        Box::new(YZRect::new(0., 555., 0., 555., 0., red.clone())),
-        Box::new(XZRect::new(213., 343., 227., 332., 554., light.clone())),
-        flip_normals(XZRect::new(0., 555., 0., 555., 555., white.clone())),
-        Box::new(XZRect::new(0., 555., 0., 555., 0., white.clone())),
-        flip_normals(XYRect::new(0., 555., 0., 555., 555., white.clone())),
-        Box::new(Translate::new(
-            RotateY::new(
-                CornellBox::new((0, 0, 0), (165, 165, 165), white.clone()),
-                -18.,
-            ),
-            (130, 0, 65),
-        )),
-        Box::new(Translate::new(
-            RotateY::new(
-                CornellBox::new((0, 0, 0), (165, 330, 165), white.clone()),
-                15.,
-            ),
-            (265, 0, 295),
-        )),
-    ]
+fn parse_aov(s: &str) -> Result<Aov, String> {
+    Aov::from_name(s).ok_or_else(|| {
+        let names: Vec<_> = Aov::ALL.iter().map(|aov| aov.name()).collect();
+        format!("expected one of {}", names.join(", "))
+    })
 }
 
-fn color(r: &Ray, world: &dyn Hittable, depth: i32) -> Vec3 {
-    if let Some(rec) = world.hit(r, 0.001, std::f32::MAX) {
-        let emitted = rec.mat.emitted(rec.u, rec.v, rec.p);
-        match rec.mat.scatter(&r, &rec) {
-            Some((attenuation, scattered)) if depth < 50 => {
-                emitted + attenuation * color(&scattered, world, depth + 1)
+#[derive(Parser)]
+#[command(about = "Renders a scene with a path tracer")]
+struct Args {
+    /// Scene description file; settings in it are overridden by any flags
+    file: Option<PathBuf>,
+
+    /// Built-in scene to render when no scene file is given
+    #[arg(short, long, default_value = "cornell_box")]
+    scene: String,
+
+    /// List the built-in scenes and exit
+    #[arg(long)]
+    list_scenes: bool,
+
+    /// Output image path; .exr, .pfm and .hdr files keep the full dynamic range
+    #[arg(short, long, default_value = "out.png")]
+    output: PathBuf,
+
+    /// Tone mapping operator for 8-bit output: clamp, reinhard,
+    /// extended_reinhard, aces or hable
+    #[arg(long, value_parser = parse_operator)]
+    tonemap: Option<Operator>,
+
+    /// Exposure adjustment in stops, applied before tone mapping
+    #[arg(long, allow_hyphen_values = true)]
+    exposure: Option<f32>,
+
+    /// Luminance mapped to white by extended_reinhard
+    #[arg(long)]
+    white: Option<f32>,
+
+    /// Image width in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    width: Option<u32>,
+
+    /// Image height in pixels
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    height: Option<u32>,
+
+    /// Samples per pixel
+    #[arg(short = 'n', long)]
+    samples: Option<u32>,
+
+    /// Sample adaptively, stopping at a pixel once the standard error of its
+    /// luminance is within this fraction of the mean; --samples becomes the
+    /// most any pixel gets
+    #[arg(long)]
+    adaptive_threshold: Option<f32>,
+
+    /// Samples every pixel gets before adaptive sampling can stop
+    #[arg(long)]
+    min_samples: Option<u32>,
+
+    /// Pixel reconstruction filter: box, tent, gaussian, mitchell or lanczos
+    #[arg(long, value_parser = parse_filter)]
+    filter: Option<FilterKind>,
+
+    /// Radius of the filter in pixels [default: 0.5 for box, 1 for tent, 1.5
+    /// for gaussian, 2 for mitchell and lanczos]
+    #[arg(long)]
+    filter_radius: Option<f32>,
+
+    /// Maximum number of bounces per path
+    #[arg(long)]
+    max_depth: Option<i32>,
+
+    /// AOVs to render, comma-separated: albedo, normal, depth, position, uv,
+    /// id, samples. They are added to .exr output and otherwise written next to it as
+    /// <name>.<aov>.<ext>
+    #[arg(long, value_parser = parse_aov, value_delimiter = ',')]
+    aovs: Vec<Aov>,
+
+    /// Denoise the result, guided by the albedo, normal and depth AOVs
+    #[arg(long)]
+    denoise: bool,
+
+    /// Render in passes of this many samples per pixel, saving the image
+    /// after each one
+    #[arg(long)]
+    pass_samples: Option<u32>,
+
+    /// Stop after this many seconds, even if not all samples are done
+    #[arg(long)]
+    time_limit: Option<f32>,
+
+    /// Save the image at most this often, in seconds, during a progressive
+    /// render [default: every pass]
+    #[arg(long)]
+    snapshot_interval: Option<f32>,
+
+    /// Save the render state here after every snapshot and at the end, so
+    /// that it can be resumed
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Continue from a checkpoint taken with the same scene and settings
+    #[arg(long)]
+    resume: Option<PathBuf>,
+
+    /// How the random numbers of each pixel's samples are spread out:
+    /// independent, stratified, halton or sobol
+    #[arg(long, value_parser = parse_sampler)]
+    sampler: Option<SamplerKind>,
+
+    /// Seed for the random numbers of every sample, and for the layout of the
+    /// random scene
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Number of render threads [default: one per core]
+    #[arg(short = 'j', long)]
+    threads: Option<usize>,
+
+    /// BVH construction method: median or sah
+    #[arg(long, value_parser = parse_split_method, default_value = "sah")]
+    bvh: SplitMethod,
+
+    /// Camera position, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookfrom: Option<Vec3>,
+
+    /// Point the camera looks at, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    lookat: Option<Vec3>,
+
+    /// Camera up direction, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    vup: Option<Vec3>,
+
+    /// Vertical field of view in degrees
+    #[arg(long)]
+    vfov: Option<f32>,
+
+    /// Lens aperture; 0 is a pinhole camera
+    #[arg(long)]
+    aperture: Option<f32>,
+
+    /// Distance to the plane in focus
+    #[arg(long)]
+    focus_dist: Option<f32>,
+}
+
+fn main() {
+    let args = Args::parse();
+    if args.list_scenes {
+        for (name, description) in SCENES {
+            println!("{:<20}{}", name, description);
+        }
+        return;
+    }
+
+    let scene = match &args.file {
+        Some(path) => Scene::load(path).unwrap_or_else(|err| {
+            eprintln!("{}", err);
+            process::exit(1);
+        }),
+        None => match scenes::builtin(&args.scene, args.seed.unwrap_or_default()) {
+            Ok(Some(scene)) => scene,
+            Ok(None) => {
+                eprintln!("unknown scene `{}`; see --list-scenes", args.scene);
+                process::exit(1);
+            }
+            Err(err) => {
+                eprintln!("{}", err);
+                process::exit(1);
             }
-            _ => emitted,
+        },
+    };
+    let Scene {
+        mut world,
+        mut camera,
+        render: mut settings,
+        mut tonemap,
+    } = scene;
+
+    settings.width = args.width.unwrap_or(settings.width);
+    settings.height = args.height.unwrap_or(settings.height);
+    if settings.width == 0 || settings.height == 0 {
+        eprintln!("image width and height must be positive");
+        process::exit(1);
+    }
+    settings.samples = args.samples.unwrap_or(settings.samples);
+    settings.max_depth = args.max_depth.unwrap_or(settings.max_depth);
+    settings.adaptive_threshold = args.adaptive_threshold.or(settings.adaptive_threshold);
+    settings.min_samples = args.min_samples.unwrap_or(settings.min_samples);
+    if let Some(kind) = args.filter {
+        // A new kind of filter comes with its own default radius.
+        if kind != settings.filter.kind {
+            settings.filter.radius = None;
+        }
+        settings.filter.kind = kind;
+    }
+    settings.filter.radius = args.filter_radius.or(settings.filter.radius);
+    settings.sampler = args.sampler.unwrap_or(settings.sampler);
+    settings.seed = args.seed.unwrap_or(settings.seed);
+    settings.denoise |= args.denoise;
+    settings.pass_samples = args.pass_samples.or(settings.pass_samples);
+    settings.time_limit = args.time_limit.or(settings.time_limit);
+    settings.snapshot_interval = args.snapshot_interval.or(settings.snapshot_interval);
+    if !args.aovs.is_empty() {
+        settings.aovs = args.aovs.clone();
+    }
+    tonemap.operator = args.tonemap.unwrap_or(tonemap.operator);
+    tonemap.exposure = args.exposure.unwrap_or(tonemap.exposure);
+    tonemap.white = args.white.unwrap_or(tonemap.white);
+    camera.lookfrom = args.lookfrom.unwrap_or(camera.lookfrom);
+    camera.lookat = args.lookat.unwrap_or(camera.lookat);
+    camera.vup = args.vup.unwrap_or(camera.vup);
+    camera.vfov = args.vfov.unwrap_or(camera.vfov);
+    camera.aperture = args.aperture.unwrap_or(camera.aperture);
+    camera.focus_dist = args.focus_dist.unwrap_or(camera.focus_dist);
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build_global()
+            .unwrap();
+    }
+
+    let (nx, ny) = (settings.width, settings.height);
+    let world = BVH::with_split(&mut world, camera.time0, camera.time1, args.bvh);
+    eprintln!("BVH: {}", world.stats());
+
+    let cam = camera.build((nx as f32) / (ny as f32));
+    let lights = world.lights();
+    let mut renderer = Renderer::new(&world, &lights, &cam, &settings);
+    if let Some(path) = &args.resume {
+        let resumed = Checkpoint::load(path).and_then(|checkpoint| renderer.resume(checkpoint));
+        if let Err(err) = resumed {
+            eprintln!("{}", err);
+            process::exit(1);
         }
-    } else {
-        Vec3::new(0., 0., 0.)
+        eprintln!("resuming at {} samples", renderer.samples());
+    }
+
+    let fb = renderer.run(|renderer, progress| {
+        eprintln!(
+            "{}/{} samples, {} pixels left, in {:.1}s",
+            progress.samples,
+            settings.samples,
+            progress.active_pixels,
+            progress.elapsed.as_secs_f32()
+        );
+        save(&renderer.framebuffer(), &args.output, &tonemap);
+        if let Some(path) = &args.checkpoint {
+            save_checkpoint(renderer, path);
+        }
+    });
+    save(&fb, &args.output, &tonemap);
+    if let Some(path) = &args.checkpoint {
+        save_checkpoint(&renderer, path);
     }
 }
 
-fn main() {
-    let nx = 800;
-    let ny = 800;
-    let ns = 100;
-
-    let world = BVH::new(&mut cornell_box(), 0., 1.);
-
-    let lookfrom = Vec3::new(278., 278., -800.);
-    let lookat = Vec3::new(278., 278., 0.);
-    let dist_to_focus = 10.;
-    let aperture = 0.;
-    let vfov = 40.;
-    let cam = Camera::new(
-        lookfrom,
-        lookat,
-        Vec3::new(0., 1., 0.),
-        vfov,
-        (nx as f32) / (ny as f32),
-        aperture,
-        dist_to_focus,
-        0.,
-        1.,
-    );
-
-    let mut rng = rand::thread_rng();
-    let mut imgbuf = image::ImageBuffer::new(nx, ny);
-
-    for (i, j, pixel) in imgbuf.enumerate_pixels_mut() {
-        let j = ny - j - 1; // Flip points vertically.
-        let color = (0..ns).fold(Vec3::default(), |col, _| {
-            let u = (i as f32 + rng.gen::<f32>()) / nx as f32;
-            let v = (j as f32 + rng.gen::<f32>()) / ny as f32;
-            col + color(&cam.get_ray(u, v), &world, 0)
-        }) / ns as f32;
-        let color = Vec3::new(color[0].sqrt(), color[1].sqrt(), color[2].sqrt());
-        let color = Vec3::new(255.99, 255.99, 255.99) * color;
-
-        *pixel = image::Rgb([color[0] as u8, color[1] as u8, color[2] as u8]);
+fn save_checkpoint(renderer: &Renderer, path: &Path) {
+    if let Err(err) = renderer.checkpoint().save(path) {
+        eprintln!("{}", err);
+        process::exit(1);
     }
+}
+
+// Writes the image, and any AOVs as separate images unless the output is an
+// exr file that holds them all.
+fn save(fb: &Framebuffer, output: &Path, tonemap: &ToneMapper) {
+    if let Err(err) = fb.save(output, tonemap) {
+        eprintln!("{}: {}", output.display(), err);
+        process::exit(1);
+    }
+    if output
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("exr"))
+    {
+        return;
+    }
+    for aov in fb.aovs() {
+        let path = aov_path(output, aov);
+        if let Err(err) = fb.save_aov(aov, &path) {
+            eprintln!("{}: {}", path.display(), err);
+            process::exit(1);
+        }
+    }
+}
 
-    imgbuf.save("out.png").unwrap();
+// out.png becomes out.albedo.png and so on.
+fn aov_path(output: &Path, aov: Aov) -> PathBuf {
+    let mut name = output.file_stem().unwrap_or_default().to_owned();
+    name.push(".");
+    name.push(aov.name());
+    if let Some(ext) = output.extension() {
+        name.push(".");
+        name.push(ext);
+    }
+    output.with_file_name(name)
 }
@@ -0,0 +1,219 @@
+use std::ops::Mul;
+
+use crate::vec3::Vec3;
+
+// A 4x4 matrix acting on column vectors, stored by rows. Points have an
+// implicit w of 1 and vectors one of 0, so only affine matrices, with a
+// bottom row of [0, 0, 0, 1], are meaningful here.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Mat4(pub [[f32; 4]; 4]);
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::identity()
+    }
+}
+
+impl Mat4 {
+    pub fn identity() -> Mat4 {
+        Mat4([
+            [1., 0., 0., 0.],
+            [0., 1., 0., 0.],
+            [0., 0., 1., 0.],
+            [0., 0., 0., 1.],
+        ])
+    }
+
+    // The affine matrix with the given top three rows.
+    pub fn affine(rows: [[f32; 4]; 3]) -> Mat4 {
+        Mat4([rows[0], rows[1], rows[2], [0., 0., 0., 1.]])
+    }
+
+    pub fn translate(offset: impl Into<Vec3>) -> Mat4 {
+        let o = offset.into();
+        Mat4::affine([[1., 0., 0., o[0]], [0., 1., 0., o[1]], [0., 0., 1., o[2]]])
+    }
+
+    pub fn scale(factors: impl Into<Vec3>) -> Mat4 {
+        let s = factors.into();
+        Mat4::affine([[s[0], 0., 0., 0.], [0., s[1], 0., 0.], [0., 0., s[2], 0.]])
+    }
+
+    // Rotations by angle degrees, counterclockwise looking down the axis
+    // towards the origin, the same way as RotateY.
+    pub fn rotate_x(angle: f32) -> Mat4 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Mat4::affine([[1., 0., 0., 0.], [0., cos, -sin, 0.], [0., sin, cos, 0.]])
+    }
+
+    pub fn rotate_y(angle: f32) -> Mat4 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Mat4::affine([[cos, 0., sin, 0.], [0., 1., 0., 0.], [-sin, 0., cos, 0.]])
+    }
+
+    pub fn rotate_z(angle: f32) -> Mat4 {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        Mat4::affine([[cos, -sin, 0., 0.], [sin, cos, 0., 0.], [0., 0., 1., 0.]])
+    }
+
+    pub fn transpose(&self) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = self.0[j][i];
+            }
+        }
+        Mat4(m)
+    }
+
+    // Gauss-Jordan elimination with partial pivoting, done in f64. None if
+    // the matrix is singular, e.g. a scale by 0.
+    pub fn inverse(&self) -> Option<Mat4> {
+        // Each row of the matrix followed by the same row of the identity.
+        let mut a = [[0f64; 8]; 4];
+        for (i, (row, src)) in a.iter_mut().zip(self.0.iter()).enumerate() {
+            for (x, y) in row.iter_mut().zip(src.iter()) {
+                *x = *y as f64;
+            }
+            row[4 + i] = 1.;
+        }
+        for col in 0..4 {
+            let pivot = (col..4)
+                .max_by(|&i, &j| a[i][col].abs().partial_cmp(&a[j][col].abs()).unwrap())
+                .unwrap();
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            let scale = 1. / a[col][col];
+            for x in &mut a[col] {
+                *x *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    let pivot_row = a[col];
+                    for (x, p) in a[row].iter_mut().zip(pivot_row.iter()) {
+                        *x -= factor * p;
+                    }
+                }
+            }
+        }
+        let mut m = [[0.; 4]; 4];
+        for (row, src) in m.iter_mut().zip(a.iter()) {
+            for (x, y) in row.iter_mut().zip(src[4..].iter()) {
+                *x = *y as f32;
+            }
+        }
+        Some(Mat4(m))
+    }
+
+    // Determinant of the upper 3x3 block, by which the matrix scales volumes.
+    pub fn determinant3(&self) -> f32 {
+        let m = &self.0;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point(&self, p: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * p[0] + m[0][1] * p[1] + m[0][2] * p[2] + m[0][3],
+            m[1][0] * p[0] + m[1][1] * p[1] + m[1][2] * p[2] + m[1][3],
+            m[2][0] * p[0] + m[2][1] * p[1] + m[2][2] * p[2] + m[2][3],
+        )
+    }
+
+    pub fn transform_vector(&self, v: Vec3) -> Vec3 {
+        let m = &self.0;
+        Vec3::new(
+            m[0][0] * v[0] + m[0][1] * v[1] + m[0][2] * v[2],
+            m[1][0] * v[0] + m[1][1] * v[1] + m[1][2] * v[2],
+            m[2][0] * v[0] + m[2][1] * v[1] + m[2][2] * v[2],
+        )
+    }
+}
+
+// Composition: (a * b) applies b first, then a.
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, other: Mat4) -> Mat4 {
+        let mut m = [[0.; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            for (j, x) in row.iter_mut().enumerate() {
+                *x = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Mat4(m)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: &Mat4, b: &Mat4) {
+        for (row_a, row_b) in a.0.iter().zip(&b.0) {
+            for (x, y) in row_a.iter().zip(row_b) {
+                assert!((x - y).abs() < 1e-5, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    fn placement() -> Mat4 {
+        let shear = Mat4::affine([[1., 0.5, 0., 0.], [0., 1., 0., 0.], [0.25, 0., 1., 0.]]);
+        Mat4::translate((3., -2., 5.))
+            * Mat4::rotate_y(30.)
+            * Mat4::rotate_x(-70.)
+            * shear
+            * Mat4::scale((2., 0.5, 4.))
+    }
+
+    #[test]
+    fn inverse_undoes_the_matrix() {
+        let m = placement();
+        let inverse = m.inverse().unwrap();
+        assert_close(&(m * inverse), &Mat4::identity());
+        assert_close(&(inverse * m), &Mat4::identity());
+        assert_close(&inverse.inverse().unwrap(), &m);
+
+        let p = Vec3::new(0.3, -1.2, 7.);
+        let back = inverse.transform_point(m.transform_point(p));
+        assert!((back - p).length() < 1e-5);
+    }
+
+    #[test]
+    fn inverse_of_simple_matrices() {
+        assert_eq!(Mat4::identity().inverse(), Some(Mat4::identity()));
+        assert_eq!(
+            Mat4::translate((1., 2., 3.)).inverse(),
+            Some(Mat4::translate((-1., -2., -3.)))
+        );
+        assert_eq!(
+            Mat4::scale((2., 4., 0.5)).inverse(),
+            Some(Mat4::scale((0.5, 0.25, 2.)))
+        );
+        assert_close(
+            &Mat4::rotate_z(40.).inverse().unwrap(),
+            &Mat4::rotate_z(40.).transpose(),
+        );
+    }
+
+    #[test]
+    fn singular_matrices_have_no_inverse() {
+        assert_eq!(Mat4::scale((1., 0., 1.)).inverse(), None);
+        // Rows that are multiples of each other.
+        let flat = Mat4::affine([[1., 2., 3., 0.], [2., 4., 6., 1.], [0., 0., 1., 0.]]);
+        assert_eq!(flat.inverse(), None);
+    }
+
+    #[test]
+    fn determinant_is_the_volume_scale() {
+        assert!((Mat4::scale((2., 0.5, 4.)).determinant3() - 4.).abs() < 1e-6);
+        assert!((Mat4::rotate_x(33.).determinant3() - 1.).abs() < 1e-6);
+        assert!((placement().determinant3() - 4.).abs() < 1e-5);
+        assert!((Mat4::scale((-1., 1., 1.)).determinant3() + 1.).abs() < 1e-6);
+    }
+}
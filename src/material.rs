@@ -1,26 +1,54 @@
-use rand::prelude::*;
-
 use crate::hittable::HitRecord;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::texture::Texture;
 use crate::vec3::Vec3;
 
+// The kind of scattering lobe a direction was sampled from. Specular lobes are
+// delta distributions that light sampling can never produce.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lobe {
+    Diffuse,
+    Glossy,
+    Specular,
+}
+
+pub struct ScatterRecord {
+    pub attenuation: Vec3,
+    pub scattered: Ray,
+    // Solid angle density of the scattered direction; 0 for specular lobes.
+    pub pdf: f32,
+    pub lobe: Lobe,
+}
+
 #[derive(Clone, Debug)]
 pub enum Material {
     Glass(f32),
     Diffuse(Texture),
+    // Scatters uniformly in all directions; the phase function of volumes.
+    Isotropic(Texture),
     Light(Texture),
     Metal(Vec3, f32),
+    // Lets rays through unchanged. Heterogeneous media return it for null
+    // collisions, which the renderer passes straight through.
+    Null,
 }
 
-fn random_in_unit_sphere() -> Vec3 {
-    let mut p = Vec3::new(1., 1., 1.);
-    let mut rng = thread_rng();
-    while p.squared_length() >= 1. {
-        let v = Vec3::new(rng.gen::<f32>(), rng.gen::<f32>(), rng.gen::<f32>());
-        p = 2. * v - Vec3::new(1., 1., 1.);
-    }
-    p
+// Maps a point of the unit square to a uniformly distributed direction.
+fn uniform_sphere((u, v): (f32, f32)) -> Vec3 {
+    let z = 1. - 2. * u;
+    let r = (1. - z * z).max(0.).sqrt();
+    let phi = 2. * std::f32::consts::PI * v;
+    Vec3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+fn random_in_unit_sphere(sampler: &mut dyn Sampler) -> Vec3 {
+    let direction = uniform_sphere(sampler.get_2d());
+    sampler.get_1d().cbrt() * direction
+}
+
+fn random_unit_vector(sampler: &mut dyn Sampler) -> Vec3 {
+    uniform_sphere(sampler.get_2d())
 }
 
 fn reflect(v: Vec3, n: Vec3) -> Vec3 {
@@ -38,13 +66,37 @@ fn refract(v: Vec3, n: Vec3, ni_over_nt: f32) -> Option<Vec3> {
     }
 }
 
+// Density of the direction of reflected + fuzz * p, where p is uniform in the
+// unit ball: integrate t^2 along the chord the direction cuts through the ball
+// of radius fuzz around reflected, divided by the ball's volume.
+fn fuzzy_reflection_pdf(reflected: Vec3, fuzz: f32, direction: Vec3) -> f32 {
+    let w = direction.unit_vector();
+    let b = w.dot(reflected);
+    let c = reflected.squared_length() - fuzz * fuzz;
+    let discriminant = b * b - c;
+    if discriminant <= 0. {
+        return 0.;
+    }
+    let t_far = b + discriminant.sqrt();
+    if t_far <= 0. {
+        return 0.;
+    }
+    let t_near = (b - discriminant.sqrt()).max(0.);
+    (t_far.powi(3) - t_near.powi(3)) / (4. * std::f32::consts::PI * fuzz.powi(3))
+}
+
 fn schlick(cosine: f32, ref_idx: f32) -> f32 {
     let r0 = ((1. - ref_idx) / (1. + ref_idx)).powf(2.);
     r0 + (1. - r0) * (1. - cosine).powf(5.)
 }
 
 impl Material {
-    pub fn scatter(&self, r_in: &Ray, rec: &HitRecord) -> Option<(Vec3, Ray)> {
+    pub fn scatter(
+        &self,
+        r_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut dyn Sampler,
+    ) -> Option<ScatterRecord> {
         match self {
             Material::Glass(ref_idx) => {
                 let reflected = reflect(r_in.direction(), rec.normal);
@@ -58,44 +110,60 @@ impl Material {
                     (rec.normal, 1. / *ref_idx, cosine)
                 };
 
-                if let Some(refracted) = refract(r_in.direction(), outward_normal, ni_over_nt) {
-                    let reflect_prob = schlick(cosine, *ref_idx);
-                    if thread_rng().gen::<f32>() < reflect_prob {
-                        Some((
-                            Vec3::new(1., 1., 1.),
-                            Ray::new(rec.p, reflected, r_in.time()),
-                        ))
-                    } else {
-                        Some((
-                            Vec3::new(1., 1., 1.),
-                            Ray::new(rec.p, refracted, r_in.time()),
-                        ))
-                    }
-                } else {
-                    Some((
-                        Vec3::new(1., 1., 1.),
-                        Ray::new(rec.p, reflected, r_in.time()),
-                    ))
-                }
+                let direction = match refract(r_in.direction(), outward_normal, ni_over_nt) {
+                    Some(refracted) if sampler.get_1d() >= schlick(cosine, *ref_idx) => refracted,
+                    _ => reflected,
+                };
+                Some(ScatterRecord {
+                    attenuation: Vec3::new(1., 1., 1.),
+                    scattered: Ray::new(rec.p, direction, r_in.time()),
+                    pdf: 0.,
+                    lobe: Lobe::Specular,
+                })
             }
             Material::Diffuse(albedo) => {
-                let target = rec.p + rec.normal + random_in_unit_sphere();
-                Some((
-                    albedo.value(rec.u, rec.v, rec.p),
-                    Ray::new(rec.p, target - rec.p, r_in.time()),
-                ))
+                // Offsetting the normal by a point on the unit sphere gives an
+                // exactly cosine-weighted direction, i.e. a Lambertian surface.
+                let mut direction = rec.normal + random_unit_vector(sampler);
+                // Catch samples that almost exactly cancel out the normal.
+                if direction.squared_length() < 1e-8 {
+                    direction = rec.normal;
+                }
+                Some(ScatterRecord {
+                    attenuation: albedo.value(rec.u, rec.v, rec.p),
+                    scattered: Ray::new(rec.p, direction, r_in.time()),
+                    pdf: self.scattering_pdf(r_in, rec, direction),
+                    lobe: Lobe::Diffuse,
+                })
             }
-            Material::Light(_) => None,
+            Material::Isotropic(albedo) => Some(ScatterRecord {
+                attenuation: albedo.value(rec.u, rec.v, rec.p),
+                scattered: Ray::new(rec.p, random_unit_vector(sampler), r_in.time()),
+                pdf: 1. / (4. * std::f32::consts::PI),
+                lobe: Lobe::Diffuse,
+            }),
+            Material::Light(_) | Material::Null => None,
             Material::Metal(albedo, fuzz) => {
                 let fuzz = fuzz.min(1.);
                 let reflected = reflect(r_in.direction().unit_vector(), rec.normal);
                 let scattered = Ray::new(
                     rec.p,
-                    reflected + fuzz * random_in_unit_sphere(),
+                    reflected + fuzz * random_in_unit_sphere(sampler),
                     r_in.time(),
                 );
                 if scattered.direction().dot(rec.normal) > 0. {
-                    Some((*albedo, scattered))
+                    let (pdf, lobe) = if fuzz > 0. {
+                        let pdf = self.scattering_pdf(r_in, rec, scattered.direction());
+                        (pdf, Lobe::Glossy)
+                    } else {
+                        (0., Lobe::Specular)
+                    };
+                    Some(ScatterRecord {
+                        attenuation: *albedo,
+                        scattered,
+                        pdf,
+                        lobe,
+                    })
                 } else {
                     None
                 }
@@ -103,6 +171,62 @@ impl Material {
         }
     }
 
+    // Solid angle density with which scatter samples direction. Always 0 for
+    // specular lobes.
+    pub fn scattering_pdf(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> f32 {
+        match self {
+            Material::Diffuse(_) => {
+                let cosine = rec.normal.dot(direction.unit_vector());
+                cosine.max(0.) / std::f32::consts::PI
+            }
+            Material::Isotropic(_) => 1. / (4. * std::f32::consts::PI),
+            Material::Metal(_, fuzz) if *fuzz > 0. => {
+                if direction.dot(rec.normal) <= 0. {
+                    return 0.;
+                }
+                let reflected = reflect(r_in.direction().unit_vector(), rec.normal);
+                fuzzy_reflection_pdf(reflected, fuzz.min(1.), direction)
+            }
+            _ => 0.,
+        }
+    }
+
+    // The BSDF times the cosine term for light scattered along direction, so
+    // that scatter's attenuation is eval / scattering_pdf for its own samples.
+    pub fn eval(&self, r_in: &Ray, rec: &HitRecord, direction: Vec3) -> Vec3 {
+        match self {
+            Material::Diffuse(albedo) => {
+                albedo.value(rec.u, rec.v, rec.p) * self.scattering_pdf(r_in, rec, direction)
+            }
+            Material::Isotropic(albedo) => {
+                albedo.value(rec.u, rec.v, rec.p) * self.scattering_pdf(r_in, rec, direction)
+            }
+            Material::Metal(albedo, _) => *albedo * self.scattering_pdf(r_in, rec, direction),
+            _ => Vec3::default(),
+        }
+    }
+
+    // The color of the surface itself, independent of lighting. Glass
+    // transmits everything and lights report their emission.
+    pub fn albedo(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
+        match self {
+            Material::Diffuse(texture)
+            | Material::Isotropic(texture)
+            | Material::Light(texture) => texture.value(u, v, p),
+            Material::Metal(albedo, _) => *albedo,
+            Material::Glass(_) => Vec3::new(1., 1., 1.),
+            Material::Null => Vec3::default(),
+        }
+    }
+
+    pub fn is_light(&self) -> bool {
+        matches!(self, Material::Light(_))
+    }
+
+    pub fn is_null(&self) -> bool {
+        matches!(self, Material::Null)
+    }
+
     pub fn emitted(&self, u: f32, v: f32, p: Vec3) -> Vec3 {
         match self {
             Material::Light(emit) => emit.value(u, v, p),
@@ -110,3 +234,67 @@ impl Material {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use std::f32::consts::PI;
+
+    // Histograms the cosines of sampled directions and checks each bin gets
+    // the share scattering_pdf integrates to over the band of directions.
+    #[test]
+    fn diffuse_samples_follow_scattering_pdf() {
+        let mat = Material::Diffuse(Texture::solid((0.5, 0.5, 0.5)));
+        let normal = Vec3::new(0., 0., 1.);
+        let rec = HitRecord {
+            t: 1.,
+            p: Vec3::default(),
+            normal,
+            mat: &mat,
+            u: 0.,
+            v: 0.,
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
+        };
+        let r_in = Ray::new(Vec3::new(0., 0., 1.), Vec3::new(0., 0., -1.), 0.);
+
+        let n = 200_000;
+        let bins = 10;
+        let mut counts = vec![0; bins];
+        let mut sampler = SamplerKind::Independent.build(0, n);
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i);
+            let srec = mat.scatter(&r_in, &rec, &mut *sampler).unwrap();
+            let direction = srec.scattered.direction();
+            let pdf = mat.scattering_pdf(&r_in, &rec, direction);
+            assert!((srec.pdf - pdf).abs() <= 1e-6);
+            let cosine = normal.dot(direction.unit_vector());
+            assert!(cosine >= 0.);
+            counts[((cosine * bins as f32) as usize).min(bins - 1)] += 1;
+        }
+
+        for (bin, &count) in counts.iter().enumerate() {
+            // The band between two cosines has solid angle 2pi times their
+            // difference; integrate the pdf over it by the midpoint rule.
+            let steps = 100;
+            let width = 1. / (bins * steps) as f32;
+            let expected: f32 = (0..steps)
+                .map(|step| {
+                    let cosine = (bin * steps + step) as f32 * width + width / 2.;
+                    let sine = (1. - cosine * cosine).sqrt();
+                    let direction = Vec3::new(sine, 0., cosine);
+                    2. * PI * width * mat.scattering_pdf(&r_in, &rec, direction)
+                })
+                .sum();
+            let actual = count as f32 / n as f32;
+            assert!(
+                (actual - expected).abs() < 0.005,
+                "bin {}: {} sampled, {} expected",
+                bin,
+                actual,
+                expected
+            );
+        }
+    }
+}
@@ -2,7 +2,8 @@ use crate::aabb::{surrounding_box, AABB};
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
-use crate::sphere::get_sphere_uv;
+use crate::sampler::Sampler;
+use crate::sphere::{cone_pdf, get_sphere_uv, random_to_sphere};
 use crate::vec3::Vec3;
 
 #[derive(Clone)]
@@ -60,6 +61,8 @@ impl Hittable for MovingSphere {
                     mat: &self.mat,
                     u,
                     v,
+                    id: 0,
+                    weight: Vec3::new(1., 1., 1.),
                 });
             }
 
@@ -74,6 +77,8 @@ impl Hittable for MovingSphere {
                     mat: &self.mat,
                     u,
                     v,
+                    id: 0,
+                    weight: Vec3::new(1., 1., 1.),
                 });
             }
         }
@@ -92,4 +97,20 @@ impl Hittable for MovingSphere {
         );
         Some(surrounding_box(box0, box1))
     }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        if self.hit(&Ray::new(o, v, time), 0.001, f32::MAX).is_some() {
+            cone_pdf(self.center(time), self.radius, o)
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        random_to_sphere(self.center(time), self.radius, o, sampler)
+    }
 }
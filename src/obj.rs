@@ -0,0 +1,568 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::hittable::Hittable;
+use crate::material::Material;
+use crate::texture::Texture;
+use crate::triangle::TriangleMesh;
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum ObjError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Image {
+        path: PathBuf,
+        err: image::ImageError,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        msg: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ObjError::Io { path, err } => write!(f, "{}: {}", path.display(), err),
+            ObjError::Image { path, err } => write!(f, "{}: {}", path.display(), err),
+            ObjError::Parse { path, line, msg } => {
+                write!(f, "{}:{}: {}", path.display(), line, msg)
+            }
+        }
+    }
+}
+
+impl Error for ObjError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ObjError::Io { err, .. } => Some(err),
+            ObjError::Image { err, .. } => Some(err),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+// Loads a Wavefront OBJ file along with any MTL libraries it references,
+// producing one triangle per face. Faces are grouped into one TriangleMesh per
+// material so that vertices are shared between the faces that use them.
+pub fn load_obj<P: AsRef<Path>>(path: P) -> Result<Vec<Box<dyn Hittable>>, ObjError> {
+    let path = path.as_ref();
+    let dir = path.parent().unwrap_or_else(|| Path::new(""));
+    let source = read_to_string(path)?;
+
+    let mut positions = Vec::new();
+    let mut normals = Vec::new();
+    let mut uvs = Vec::new();
+    let mut materials = HashMap::new();
+    let mut builders: Vec<(String, MeshBuilder)> = Vec::new();
+    let mut current = String::new();
+
+    for (idx, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(path, idx + 1, line);
+        let keyword = match parser.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        match keyword {
+            "v" => {
+                let p = parser.vec3()?;
+                // Skip the optional w component or vertex colour.
+                parser.rest();
+                positions.push(p);
+            }
+            "vt" => {
+                let u = parser.float()?;
+                let v = if parser.is_empty() {
+                    0.
+                } else {
+                    parser.float()?
+                };
+                parser.rest();
+                uvs.push((u, v));
+            }
+            "vn" => {
+                let n = parser.vec3()?;
+                parser.end()?;
+                normals.push(n);
+            }
+            "f" => {
+                let refs = parser
+                    .rest()
+                    .iter()
+                    .map(|r| {
+                        parse_vertex_ref(r, positions.len(), uvs.len(), normals.len())
+                            .map_err(|msg| parser.error(msg))
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                if refs.len() < 3 {
+                    return Err(parser.error("face needs at least three vertices".into()));
+                }
+
+                let builder = match builders.iter_mut().position(|(name, _)| *name == current) {
+                    Some(i) => &mut builders[i].1,
+                    None => {
+                        let builder = MeshBuilder {
+                            first_line: idx + 1,
+                            ..MeshBuilder::default()
+                        };
+                        builders.push((current.clone(), builder));
+                        &mut builders.last_mut().unwrap().1
+                    }
+                };
+                // Triangulate polygons as a fan around the first vertex.
+                let first = builder.vertex(refs[0], &positions, &uvs, &normals);
+                for pair in refs[1..].windows(2) {
+                    let a = builder.vertex(pair[0], &positions, &uvs, &normals);
+                    let b = builder.vertex(pair[1], &positions, &uvs, &normals);
+                    builder.indices.push([first, a, b]);
+                }
+            }
+            "usemtl" => {
+                current = parser.name()?;
+            }
+            "mtllib" => {
+                for file in parser.rest() {
+                    materials.extend(load_mtl(&dir.join(file))?);
+                }
+            }
+            // Grouping, smoothing groups and anything else we don't render.
+            _ => {}
+        }
+    }
+
+    let mut world = Vec::new();
+    for (name, builder) in builders {
+        let mat = match materials.get(&name) {
+            Some(mtl) => mtl.to_material(dir)?,
+            None if name.is_empty() => Material::Diffuse(Texture::solid((0.8, 0.8, 0.8))),
+            None => {
+                return Err(ObjError::Parse {
+                    path: path.to_owned(),
+                    line: builder.first_line,
+                    msg: format!("undefined material `{}`", name),
+                })
+            }
+        };
+        world.extend(builder.build(mat).into_triangles());
+    }
+    Ok(world)
+}
+
+fn read_to_string(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|err| ObjError::Io {
+        path: path.to_owned(),
+        err,
+    })
+}
+
+// Resolves an OBJ face vertex of the form v, v/vt, v//vn or v/vt/vn into
+// zero-based indices. Negative indices count back from the latest element.
+fn parse_vertex_ref(
+    s: &str,
+    npositions: usize,
+    nuvs: usize,
+    nnormals: usize,
+) -> Result<VertexRef, String> {
+    fn index(s: &str, len: usize, what: &str) -> Result<usize, String> {
+        let i = s
+            .parse::<isize>()
+            .map_err(|_| format!("invalid {} index `{}`", what, s))?;
+        let resolved = if i < 0 { len as isize + i } else { i - 1 };
+        if i == 0 || resolved < 0 || resolved >= len as isize {
+            Err(format!("{} index {} out of range", what, i))
+        } else {
+            Ok(resolved as usize)
+        }
+    }
+
+    let mut parts = s.split('/');
+    let position = index(parts.next().unwrap_or(""), npositions, "vertex")?;
+    let uv = match parts.next() {
+        Some("") | None => None,
+        Some(vt) => Some(index(vt, nuvs, "texture coordinate")?),
+    };
+    let normal = match parts.next() {
+        Some("") | None => None,
+        Some(vn) => Some(index(vn, nnormals, "normal")?),
+    };
+    if parts.next().is_some() {
+        return Err(format!("invalid face vertex `{}`", s));
+    }
+    Ok((position, uv, normal))
+}
+
+type VertexRef = (usize, Option<usize>, Option<usize>);
+
+#[derive(Default)]
+struct MeshBuilder {
+    lookup: HashMap<VertexRef, usize>,
+    positions: Vec<Vec3>,
+    normals: Vec<Option<Vec3>>,
+    uvs: Vec<Option<(f32, f32)>>,
+    indices: Vec<[usize; 3]>,
+    first_line: usize,
+}
+
+impl MeshBuilder {
+    fn vertex(
+        &mut self,
+        r: VertexRef,
+        positions: &[Vec3],
+        uvs: &[(f32, f32)],
+        normals: &[Vec3],
+    ) -> usize {
+        let next = self.positions.len();
+        let index = *self.lookup.entry(r).or_insert(next);
+        if index == next {
+            let (p, uv, n) = r;
+            self.positions.push(positions[p]);
+            self.uvs.push(uv.map(|i| uvs[i]));
+            self.normals.push(n.map(|i| normals[i]));
+        }
+        index
+    }
+
+    fn build(self, mat: Material) -> TriangleMesh {
+        // Shading normals are only usable if every vertex has one, while
+        // missing texture coordinates just default to the origin.
+        let normals = self.normals.iter().cloned().collect::<Option<Vec<_>>>();
+        let uvs = if self.uvs.iter().any(Option::is_some) {
+            self.uvs.iter().map(|uv| uv.unwrap_or((0., 0.))).collect()
+        } else {
+            Vec::new()
+        };
+        TriangleMesh::new(
+            self.positions,
+            normals.unwrap_or_default(),
+            uvs,
+            self.indices,
+            mat,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Mtl {
+    kd: Option<Vec3>,
+    ks: Option<Vec3>,
+    ke: Option<Vec3>,
+    ni: Option<f32>,
+    ns: Option<f32>,
+    d: Option<f32>,
+    illum: Option<u32>,
+    map_kd: Option<String>,
+}
+
+impl Mtl {
+    // MTL describes a Phong-style model, so pick the closest of our materials:
+    // anything emissive is a light, transparent or refractive illumination
+    // models become glass, mirror-like ones become metal and the rest diffuse.
+    fn to_material(&self, dir: &Path) -> Result<Material, ObjError> {
+        let black = Vec3::default();
+        let kd = self.kd.unwrap_or_else(|| Vec3::new(0.8, 0.8, 0.8));
+        let ks = self.ks.unwrap_or(black);
+        let ke = self.ke.unwrap_or(black);
+
+        if ke.squared_length() > 0. {
+            return Ok(Material::Light(Texture::solid(ke)));
+        }
+        let transparent = self.d.is_some_and(|d| d < 1.);
+        if transparent || [4, 6, 7].contains(&self.illum.unwrap_or(0)) {
+            return Ok(Material::Glass(self.ni.unwrap_or(1.5)));
+        }
+        let mirror = self.illum == Some(3)
+            || (ks.squared_length() > 0. && kd.squared_length() == 0. && self.map_kd.is_none());
+        if mirror {
+            // Convert the Phong exponent to a roughness; Ns = 0 is fully fuzzy.
+            let fuzz = (2. / (self.ns.unwrap_or(0.) + 2.)).sqrt();
+            return Ok(Material::Metal(ks, fuzz));
+        }
+
+        let texture = match &self.map_kd {
+            Some(file) => {
+                let path = dir.join(file);
+                Texture::image(&path).map_err(|err| ObjError::Image { path, err })?
+            }
+            None => Texture::solid(kd),
+        };
+        Ok(Material::Diffuse(texture))
+    }
+}
+
+fn load_mtl(path: &Path) -> Result<HashMap<String, Mtl>, ObjError> {
+    let source = read_to_string(path)?;
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, Mtl)> = None;
+
+    for (idx, line) in source.lines().enumerate() {
+        let mut parser = LineParser::new(path, idx + 1, line);
+        let keyword = match parser.next() {
+            Some(keyword) => keyword,
+            None => continue,
+        };
+
+        if keyword == "newmtl" {
+            let name = parser.name()?;
+            if let Some((name, mtl)) = current.replace((name, Mtl::default())) {
+                materials.insert(name, mtl);
+            }
+            continue;
+        }
+
+        let mtl = match &mut current {
+            Some((_, mtl)) => mtl,
+            None => return Err(parser.error(format!("`{}` before any newmtl", keyword))),
+        };
+        match keyword {
+            "Kd" => mtl.kd = Some(parser.color()?),
+            "Ks" => mtl.ks = Some(parser.color()?),
+            "Ke" => mtl.ke = Some(parser.color()?),
+            "Ni" => mtl.ni = Some(parser.single_float()?),
+            "Ns" => mtl.ns = Some(parser.single_float()?),
+            "d" => mtl.d = Some(parser.single_float()?),
+            "Tr" => mtl.d = Some(1. - parser.single_float()?),
+            "illum" => {
+                let s = parser.name()?;
+                let illum = s
+                    .parse()
+                    .map_err(|_| parser.error(format!("invalid illumination model `{}`", s)))?;
+                mtl.illum = Some(illum);
+            }
+            "map_Kd" => {
+                // Texture options come first; the file name is always last.
+                match parser.rest().last() {
+                    Some(file) => mtl.map_kd = Some(file.to_string()),
+                    None => return Err(parser.error("missing texture file name".into())),
+                }
+            }
+            _ => {}
+        }
+    }
+
+    if let Some((name, mtl)) = current {
+        materials.insert(name, mtl);
+    }
+    Ok(materials)
+}
+
+struct LineParser<'a> {
+    path: &'a Path,
+    line: usize,
+    tokens: std::iter::Peekable<std::str::SplitWhitespace<'a>>,
+}
+
+impl<'a> LineParser<'a> {
+    fn new(path: &'a Path, line: usize, text: &'a str) -> LineParser<'a> {
+        let text = text.split('#').next().unwrap_or("");
+        LineParser {
+            path,
+            line,
+            tokens: text.split_whitespace().peekable(),
+        }
+    }
+
+    fn error(&self, msg: String) -> ObjError {
+        ObjError::Parse {
+            path: self.path.to_owned(),
+            line: self.line,
+            msg,
+        }
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        self.tokens.next()
+    }
+
+    fn is_empty(&mut self) -> bool {
+        self.tokens.peek().is_none()
+    }
+
+    fn rest(&mut self) -> Vec<&'a str> {
+        self.tokens.by_ref().collect()
+    }
+
+    fn end(&mut self) -> Result<(), ObjError> {
+        match self.tokens.next() {
+            Some(tok) => Err(self.error(format!("unexpected `{}`", tok))),
+            None => Ok(()),
+        }
+    }
+
+    fn name(&mut self) -> Result<String, ObjError> {
+        let name = self.rest().join(" ");
+        if name.is_empty() {
+            Err(self.error("missing name".into()))
+        } else {
+            Ok(name)
+        }
+    }
+
+    fn float(&mut self) -> Result<f32, ObjError> {
+        match self.tokens.next() {
+            Some(tok) => tok
+                .parse()
+                .map_err(|_| self.error(format!("invalid number `{}`", tok))),
+            None => Err(self.error("expected a number".into())),
+        }
+    }
+
+    fn single_float(&mut self) -> Result<f32, ObjError> {
+        let x = self.float()?;
+        self.end()?;
+        Ok(x)
+    }
+
+    fn vec3(&mut self) -> Result<Vec3, ObjError> {
+        Ok(Vec3::new(self.float()?, self.float()?, self.float()?))
+    }
+
+    // MTL colours may give a single value to be used for all three channels.
+    fn color(&mut self) -> Result<Vec3, ObjError> {
+        if self.tokens.peek() == Some(&"spectral") || self.tokens.peek() == Some(&"xyz") {
+            let tok = self.tokens.next().unwrap();
+            return Err(self.error(format!("unsupported `{}` colour", tok)));
+        }
+        let r = self.float()?;
+        if self.is_empty() {
+            return Ok(Vec3::new(r, r, r));
+        }
+        let c = Vec3::new(r, self.float()?, self.float()?);
+        self.end()?;
+        Ok(c)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Writes files into a directory of their own and loads the first.
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<Vec<Box<dyn Hittable>>, ObjError> {
+        let dir = std::env::temp_dir().join(format!("obj-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let result = load_obj(dir.join(files[0].0));
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn parse_error(result: Result<Vec<Box<dyn Hittable>>, ObjError>) -> (String, usize, String) {
+        match result {
+            Err(ObjError::Parse { path, line, msg }) => {
+                let file = path.file_name().unwrap().to_string_lossy().into_owned();
+                (file, line, msg)
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("loaded"),
+        }
+    }
+
+    #[test]
+    fn vertex_refs() {
+        assert_eq!(parse_vertex_ref("2", 3, 0, 0), Ok((1, None, None)));
+        assert_eq!(parse_vertex_ref("1/2", 3, 2, 0), Ok((0, Some(1), None)));
+        assert_eq!(parse_vertex_ref("3//1", 3, 0, 1), Ok((2, None, Some(0))));
+        assert_eq!(
+            parse_vertex_ref("-1/-2/-1", 3, 2, 4),
+            Ok((2, Some(0), Some(3)))
+        );
+        assert!(parse_vertex_ref("0", 3, 0, 0).is_err());
+        assert!(parse_vertex_ref("4", 3, 0, 0).is_err());
+        assert!(parse_vertex_ref("-4", 3, 0, 0).is_err());
+        assert!(parse_vertex_ref("1/1/1/1", 3, 1, 1).is_err());
+        assert!(parse_vertex_ref("x", 3, 0, 0).is_err());
+    }
+
+    #[test]
+    fn polygons_are_triangulated_per_material() {
+        let obj = "\
+mtllib box.mtl
+# a quad and a triangle, with comments and attributes we skip
+v 0 0 0
+v 1 0 0 1.0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1
+vn 0 0 1
+g quad
+s off
+f 1/1/1 2/2/1 3//1 4//1
+usemtl glow
+f -4 -3 -2 # counting back from the last vertex
+";
+        let mtl = "newmtl glow\nKe 4\n";
+        let triangles = load("triangulate", &[("box.obj", obj), ("box.mtl", mtl)]).unwrap();
+        assert_eq!(triangles.len(), 3);
+        let lights = triangles.iter().filter(|t| t.is_emissive()).count();
+        assert_eq!(lights, 1);
+    }
+
+    #[test]
+    fn errors_give_the_line_they_are_on() {
+        let cases = [
+            (
+                "v 0 0 0\nv 1 0 0\nv 0 1 0\n\nf 1 2 4\n",
+                5,
+                "vertex index 4 out of range",
+            ),
+            ("v 0 0 0\nv 1 0 x\n", 2, "invalid number `x`"),
+            (
+                "v 0 0 0\nv 1 0 0\nf 1 2\n",
+                3,
+                "face needs at least three vertices",
+            ),
+            ("vn 0 0 1 1\n", 1, "unexpected `1`"),
+            ("usemtl\n", 1, "missing name"),
+        ];
+        for (obj, line, msg) in cases {
+            assert_eq!(
+                parse_error(load("errors", &[("bad.obj", obj)])),
+                ("bad.obj".to_string(), line, msg.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn undefined_material_is_reported_at_its_first_face() {
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nusemtl missing\n\nf 1 2 3\nf 3 2 1\n";
+        assert_eq!(
+            parse_error(load("undefined", &[("m.obj", obj)])),
+            (
+                "m.obj".to_string(),
+                6,
+                "undefined material `missing`".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn mtl_errors_name_the_mtl_file() {
+        let obj = "mtllib m.mtl\n";
+        let mtl = "# materials\nKd 1 1 1\n";
+        assert_eq!(
+            parse_error(load("mtl", &[("m.obj", obj), ("m.mtl", mtl)])),
+            ("m.mtl".to_string(), 2, "`Kd` before any newmtl".to_string())
+        );
+        let mtl = "newmtl a\nKd spectral x.spd\n";
+        assert_eq!(
+            parse_error(load("mtl-colour", &[("m.obj", obj), ("m.mtl", mtl)])),
+            (
+                "m.mtl".to_string(),
+                2,
+                "unsupported `spectral` colour".to_string()
+            )
+        );
+    }
+}
@@ -1,24 +1,27 @@
 use lazy_static::lazy_static;
-use rand::{seq::SliceRandom, Rng};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+use rand_pcg::Pcg32;
 
 use crate::vec3::Vec3;
 
+// The tables are generated from fixed seeds, so the noise is the same in
+// every run.
 lazy_static! {
-    static ref RANVEC: Vec<Vec3> = perlin_generate(256);
-    static ref PERM_X: Vec<i32> = perlin_permutation(256);
-    static ref PERM_Y: Vec<i32> = perlin_permutation(256);
-    static ref PERM_Z: Vec<i32> = perlin_permutation(256);
+    static ref RANVEC: Vec<Vec3> = perlin_generate(256, 0);
+    static ref PERM_X: Vec<i32> = perlin_permutation(256, 1);
+    static ref PERM_Y: Vec<i32> = perlin_permutation(256, 2);
+    static ref PERM_Z: Vec<i32> = perlin_permutation(256, 3);
 }
 
-fn perlin_permutation(n: usize) -> Vec<i32> {
+fn perlin_permutation(n: usize, seed: u64) -> Vec<i32> {
     let mut p = (0..n as i32).collect::<Vec<i32>>();
-    p.shuffle(&mut rand::thread_rng());
+    p.shuffle(&mut Pcg32::seed_from_u64(seed));
     p
 }
 
-fn perlin_generate(n: usize) -> Vec<Vec3> {
+fn perlin_generate(n: usize, seed: u64) -> Vec<Vec3> {
     let mut p = Vec::with_capacity(n);
-    let mut rng = rand::thread_rng();
+    let mut rng = Pcg32::seed_from_u64(seed);
 
     for _ in 0..n {
         let x = 2. * rng.gen::<f32>() - 1.;
@@ -36,15 +39,15 @@ fn perlin_interp(c: [[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
     let vv = v * v * (3. - 2. * v);
     let ww = w * w * (3. - 2. * w);
     let mut accum = 0.;
-    for i in 0..2 {
-        for j in 0..2 {
-            for k in 0..2 {
+    for (i, ci) in c.iter().enumerate() {
+        for (j, cij) in ci.iter().enumerate() {
+            for (k, cijk) in cij.iter().enumerate() {
                 let (ii, jj, kk) = (i as f32, j as f32, k as f32);
                 let weight_v = Vec3::new(u - ii, v - jj, w - kk);
                 accum += (ii * uu + (1. - ii) * (1. - uu))
                     * (jj * vv + (1. - jj) * (1. - vv))
                     * (kk * ww + (1. - kk) * (1. - ww))
-                    * c[i][j][k].dot(weight_v);
+                    * cijk.dot(weight_v);
             }
         }
     }
@@ -0,0 +1,180 @@
+use std::ops::Neg;
+
+use crate::mat4::Mat4;
+use crate::vec3::Vec3;
+
+// A rotation as a unit quaternion w + v, which interpolates smoothly and
+// without gimbal lock.
+#[derive(Clone, Copy, Debug)]
+pub struct Quat {
+    pub w: f32,
+    pub v: Vec3,
+}
+
+impl Default for Quat {
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+impl Quat {
+    pub fn identity() -> Quat {
+        Quat {
+            w: 1.,
+            v: Vec3::new(0., 0., 0.),
+        }
+    }
+
+    // A rotation by angle degrees about axis, in the same sense as
+    // Mat4::rotate_x and friends. axis needn't be normalized.
+    pub fn from_axis_angle(axis: impl Into<Vec3>, angle: f32) -> Quat {
+        let (sin, cos) = (0.5 * angle.to_radians()).sin_cos();
+        Quat {
+            w: cos,
+            v: axis.into().unit_vector() * sin,
+        }
+    }
+
+    pub fn dot(&self, other: Quat) -> f32 {
+        self.w * other.w + self.v.dot(other.v)
+    }
+
+    pub fn conjugate(&self) -> Quat {
+        Quat {
+            w: self.w,
+            v: -self.v,
+        }
+    }
+
+    fn normalized(&self) -> Quat {
+        let length = self.dot(*self).sqrt();
+        Quat {
+            w: self.w / length,
+            v: self.v / length,
+        }
+    }
+
+    // The angle in radians of the rotation from self to other along the
+    // shorter way round, which is the way slerp goes.
+    pub fn angle_to(&self, other: Quat) -> f32 {
+        2. * self.dot(other).abs().min(1.).acos()
+    }
+
+    // Spherical linear interpolation, turning at a constant rate from a at
+    // t = 0 to b at t = 1.
+    pub fn slerp(a: Quat, b: Quat, t: f32) -> Quat {
+        // q and -q are the same rotation; take the one closer to a.
+        let (b, cos) = if a.dot(b) < 0. {
+            (-b, -a.dot(b))
+        } else {
+            (b, a.dot(b))
+        };
+        let (wa, wb) = if cos > 0.9995 {
+            // Nearly parallel, where lerping is as good and sin is tiny.
+            (1. - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1. - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+        Quat {
+            w: wa * a.w + wb * b.w,
+            v: wa * a.v + wb * b.v,
+        }
+        .normalized()
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v[0], self.v[1], self.v[2]);
+        Mat4::affine([
+            [
+                1. - 2. * (y * y + z * z),
+                2. * (x * y - w * z),
+                2. * (x * z + w * y),
+                0.,
+            ],
+            [
+                2. * (x * y + w * z),
+                1. - 2. * (x * x + z * z),
+                2. * (y * z - w * x),
+                0.,
+            ],
+            [
+                2. * (x * z - w * y),
+                2. * (y * z + w * x),
+                1. - 2. * (x * x + y * y),
+                0.,
+            ],
+        ])
+    }
+}
+
+impl Neg for Quat {
+    type Output = Quat;
+
+    fn neg(self) -> Quat {
+        Quat {
+            w: -self.w,
+            v: -self.v,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // q and -q are the same rotation.
+    fn same_rotation(a: Quat, b: Quat) -> bool {
+        a.dot(b).abs() > 1. - 1e-6
+    }
+
+    #[test]
+    fn slerp_starts_and_ends_at_its_endpoints() {
+        let a = Quat::from_axis_angle((1., 2., 0.5), 40.);
+        let b = Quat::from_axis_angle((0., -1., 3.), 170.);
+        assert!(same_rotation(Quat::slerp(a, b, 0.), a));
+        assert!(same_rotation(Quat::slerp(a, b, 1.), b));
+        // The other sign of b goes the same, shorter, way round.
+        assert!(same_rotation(
+            Quat::slerp(a, -b, 0.5),
+            Quat::slerp(a, b, 0.5)
+        ));
+    }
+
+    #[test]
+    fn slerp_turns_at_a_constant_rate() {
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle((0., 1., 0.), 120.);
+        for i in 0..=10 {
+            let t = i as f32 / 10.;
+            let q = Quat::slerp(a, b, t);
+            assert!(same_rotation(
+                q,
+                Quat::from_axis_angle((0., 1., 0.), 120. * t)
+            ));
+            assert!((a.angle_to(q) - (120. * t).to_radians()).abs() < 1e-3);
+        }
+        // Nearly equal rotations, where it falls back on lerping.
+        let c = Quat::from_axis_angle((0., 1., 0.), 0.5);
+        assert!(same_rotation(
+            Quat::slerp(a, c, 0.5),
+            Quat::from_axis_angle((0., 1., 0.), 0.25)
+        ));
+    }
+
+    #[test]
+    fn to_mat4_matches_the_axis_rotations() {
+        let p = Vec3::new(0.3, -1.2, 2.);
+        let cases = [
+            ((1., 0., 0.), Mat4::rotate_x(35.)),
+            ((0., 1., 0.), Mat4::rotate_y(35.)),
+            ((0., 0., 1.), Mat4::rotate_z(35.)),
+        ];
+        for (axis, matrix) in cases {
+            let q = Quat::from_axis_angle(axis, 35.);
+            let d = q.to_mat4().transform_point(p) - matrix.transform_point(p);
+            assert!(d.length() < 1e-5);
+        }
+    }
+}
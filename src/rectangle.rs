@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct XYRect {
@@ -34,9 +35,9 @@ impl Hittable for XYRect {
         ))
     }
 
-    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
+    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord<'a>> {
         let t = (self.k - r.origin().z()) / r.direction().z();
-        if t < t0 || t > t1 {
+        if !(t0..=t1).contains(&t) {
             return None;
         }
 
@@ -53,8 +54,25 @@ impl Hittable for XYRect {
             mat: &self.mat,
             p: r.point_at_parameter(t),
             normal: Vec3::new(0., 0., 1.),
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
         })
     }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let area = (self.x1 - self.x0) * (self.y1 - self.y0);
+        area_pdf(self, area, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let random_point = Vec3::new(lerp(self.x0, self.x1, u), lerp(self.y0, self.y1, v), self.k);
+        random_point - o
+    }
 }
 
 pub struct XZRect {
@@ -87,9 +105,9 @@ impl Hittable for XZRect {
         ))
     }
 
-    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
+    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord<'a>> {
         let t = (self.k - r.origin().y()) / r.direction().y();
-        if t < t0 || t > t1 {
+        if !(t0..=t1).contains(&t) {
             return None;
         }
 
@@ -106,8 +124,25 @@ impl Hittable for XZRect {
             mat: &self.mat,
             p: r.point_at_parameter(t),
             normal: Vec3::new(0., 1., 0.),
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
         })
     }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let area = (self.x1 - self.x0) * (self.z1 - self.z0);
+        area_pdf(self, area, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let random_point = Vec3::new(lerp(self.x0, self.x1, u), self.k, lerp(self.z0, self.z1, v));
+        random_point - o
+    }
 }
 
 pub struct YZRect {
@@ -140,9 +175,9 @@ impl Hittable for YZRect {
         ))
     }
 
-    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord> {
+    fn hit<'a>(&'a self, r: &Ray, t0: f32, t1: f32) -> Option<HitRecord<'a>> {
         let t = (self.k - r.origin().x()) / r.direction().x();
-        if t < t0 || t > t1 {
+        if !(t0..=t1).contains(&t) {
             return None;
         }
 
@@ -159,6 +194,40 @@ impl Hittable for YZRect {
             mat: &self.mat,
             p: r.point_at_parameter(t),
             normal: Vec3::new(1., 0., 0.),
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
         })
     }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        let area = (self.y1 - self.y0) * (self.z1 - self.z0);
+        area_pdf(self, area, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        let (u, v) = sampler.get_2d();
+        let random_point = Vec3::new(self.k, lerp(self.y0, self.y1, u), lerp(self.z0, self.z1, v));
+        random_point - o
+    }
+}
+
+// Converts a uniform density over a planar surface of the given area into a
+// solid angle density as seen from o.
+pub fn area_pdf(surface: &dyn Hittable, area: f32, o: Vec3, v: Vec3, time: f32) -> f32 {
+    match surface.hit(&Ray::new(o, v, time), 0.001, f32::MAX) {
+        Some(rec) => {
+            let distance_squared = rec.t * rec.t * v.squared_length();
+            let cosine = (v.dot(rec.normal) / v.length()).abs();
+            distance_squared / (cosine * area)
+        }
+        None => 0.,
+    }
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
 }
@@ -0,0 +1,740 @@
+use std::time::{Duration, Instant};
+
+use rayon::prelude::*;
+use serde::Deserialize;
+
+use crate::aov::Aov;
+use crate::camera::Camera;
+use crate::checkpoint::{Checkpoint, CheckpointError};
+use crate::denoise::Denoiser;
+use crate::filter::Filter;
+use crate::framebuffer::Framebuffer;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Lobe;
+use crate::ray::Ray;
+use crate::sampler::{Sampler, SamplerKind};
+use crate::tonemap::luminance;
+use crate::vec3::Vec3;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RenderSettings {
+    pub width: u32,
+    pub height: u32,
+    // Samples per pixel, or the most any pixel gets with adaptive sampling.
+    pub samples: u32,
+    pub max_depth: i32,
+    // Adaptive sampling: once a pixel has min_samples, stop sampling it when
+    // the standard error of its mean luminance is within this fraction of
+    // the mean.
+    pub adaptive_threshold: Option<f32>,
+    pub min_samples: u32,
+    // AOV layers to render alongside the radiance.
+    pub aovs: Vec<Aov>,
+    // Whether to filter the result with denoiser, which renders the albedo,
+    // normal and depth layers whether or not they're in aovs.
+    pub denoise: bool,
+    pub denoiser: Denoiser,
+    // Progressive rendering: samples per pixel added in each pass, a limit
+    // on the total render time in seconds, and the least time in seconds
+    // between snapshots of the image so far.
+    pub pass_samples: Option<u32>,
+    pub time_limit: Option<f32>,
+    pub snapshot_interval: Option<f32>,
+    // How samples are weighted into the pixels around them.
+    pub filter: Filter,
+    // How the random numbers of every sample are chosen, and their seed.
+    pub sampler: SamplerKind,
+    pub seed: u64,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        RenderSettings {
+            width: 800,
+            height: 800,
+            samples: 100,
+            max_depth: 50,
+            adaptive_threshold: None,
+            min_samples: 16,
+            aovs: Vec::new(),
+            denoise: false,
+            denoiser: Denoiser::default(),
+            pass_samples: None,
+            time_limit: None,
+            snapshot_interval: None,
+            filter: Filter::default(),
+            sampler: SamplerKind::Independent,
+            seed: 0,
+        }
+    }
+}
+
+fn color(
+    r: &Ray,
+    world: &dyn Hittable,
+    lights: &[&dyn Hittable],
+    max_depth: i32,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let mut radiance = Vec3::default();
+    let mut throughput = Vec3::new(1., 1., 1.);
+    let mut ray = r.clone();
+    // Density of the bounce that produced ray, or None if it couldn't have
+    // been produced by light sampling.
+    let mut bsdf_pdf: Option<f32> = None;
+
+    for depth in 0.. {
+        let rec = match trace(world, &ray) {
+            Some(rec) => rec,
+            None => break,
+        };
+        throughput *= rec.weight;
+        // Light that a BSDF sample happens to reach is weighted against the
+        // chance of light sampling having picked the same direction.
+        let emitted = rec.mat.emitted(rec.u, rec.v, rec.p);
+        let weight = match bsdf_pdf {
+            Some(pdf) => power_heuristic(pdf, light_pdf(lights, &ray)),
+            None => 1.,
+        };
+        radiance += throughput * emitted * weight;
+
+        let srec = match rec.mat.scatter(&ray, &rec, sampler) {
+            Some(srec) if depth < max_depth => srec,
+            _ => break,
+        };
+        bsdf_pdf = None;
+        if srec.lobe != Lobe::Specular && !lights.is_empty() {
+            radiance += throughput * sample_lights(&rec, &ray, world, lights, sampler);
+            bsdf_pdf = Some(srec.pdf);
+        }
+
+        throughput *= srec.attenuation;
+        ray = srec.scattered;
+    }
+
+    radiance
+}
+
+// The closest hit along r other than null collisions, which rays pass
+// straight through. The weights of those passed are folded into the hit's and
+// its t is measured from the origin of r. None if the ray escapes or is
+// absorbed on the way.
+fn trace<'a>(world: &'a dyn Hittable, r: &Ray) -> Option<HitRecord<'a>> {
+    let mut ray = r.clone();
+    let mut t = 0.;
+    let mut weight = Vec3::new(1., 1., 1.);
+    loop {
+        let rec = world.hit(&ray, 0.001, f32::MAX)?;
+        t += rec.t;
+        weight *= rec.weight;
+        if !rec.mat.is_null() {
+            return Some(HitRecord { t, weight, ..rec });
+        }
+        if weight.squared_length() <= 0. {
+            return None;
+        }
+        ray = Ray::new(rec.p, ray.direction(), ray.time());
+    }
+}
+
+fn power_heuristic(pdf: f32, other_pdf: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b > 0. {
+        a / (a + b)
+    } else {
+        0.
+    }
+}
+
+// Density of light sampling producing the direction of r from its origin. The
+// direction could have been produced by any of the lights.
+fn light_pdf(lights: &[&dyn Hittable], r: &Ray) -> f32 {
+    if lights.is_empty() {
+        return 0.;
+    }
+    let sum = lights
+        .iter()
+        .map(|light| light.pdf_value(r.origin(), r.direction(), r.time()))
+        .sum::<f32>();
+    sum / lights.len() as f32
+}
+
+// Estimates the light arriving directly at a hit by sampling a direction
+// towards a randomly chosen light and tracing a shadow ray, weighted against
+// the chance of the material sampling the same direction.
+fn sample_lights(
+    rec: &HitRecord,
+    r_in: &Ray,
+    world: &dyn Hittable,
+    lights: &[&dyn Hittable],
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let n = lights.len();
+    let light = lights[((sampler.get_1d() * n as f32) as usize).min(n - 1)];
+    let shadow = Ray::new(
+        rec.p,
+        light.random(rec.p, r_in.time(), sampler),
+        r_in.time(),
+    );
+    let f = rec.mat.eval(r_in, rec, shadow.direction());
+    if f.squared_length() <= 0. {
+        return Vec3::default();
+    }
+
+    let pdf = light_pdf(lights, &shadow);
+    if pdf <= 0. {
+        return Vec3::default();
+    }
+
+    match trace(world, &shadow) {
+        Some(hit) => {
+            let weight =
+                power_heuristic(pdf, rec.mat.scattering_pdf(r_in, rec, shadow.direction()));
+            f * hit.weight * hit.mat.emitted(hit.u, hit.v, hit.p) * weight / pdf
+        }
+        None => Vec3::default(),
+    }
+}
+
+// Running sums for one row of the image. The radiance, luminance and sample
+// counts are of the samples taken within each pixel, for adaptive sampling.
+// The image itself comes from the filtered sums, which also take in samples
+// from neighbouring pixels.
+#[derive(Clone)]
+struct Row {
+    radiance: Vec<Vec3>,
+    // Sum of squared luminance, for estimating variance.
+    luminance_sq: Vec<f32>,
+    samples: Vec<u32>,
+    filtered: Splats,
+    // One entry per AOV, each with one value per pixel. AOVs aren't filtered
+    // across pixels, which would blur the edges that guide the denoiser.
+    aovs: Vec<Vec<Vec3>>,
+}
+
+// Units per 1 of the fixed-point splat sums.
+const SPLAT_SCALE: f64 = (1u64 << 28) as f64;
+
+fn to_fixed(x: f32) -> i64 {
+    (x as f64 * SPLAT_SCALE).round() as i64
+}
+
+// Filter-weighted sums of radiance and the sums of the weights. They are kept
+// in fixed point, where addition is exact, so that the sums don't depend on
+// the order samples from neighbouring pixels arrive in. That order changes
+// with how samples are split into passes.
+#[derive(Clone)]
+struct Splats {
+    radiance: Vec<[i64; 3]>,
+    weights: Vec<i64>,
+}
+
+impl Splats {
+    fn new(nx: usize) -> Splats {
+        Splats {
+            radiance: vec![[0; 3]; nx],
+            weights: vec![0; nx],
+        }
+    }
+
+    fn splat(&mut self, p: usize, c: Vec3, weight: f32) {
+        for (sum, k) in self.radiance[p].iter_mut().zip(0..3) {
+            *sum = sum.wrapping_add(to_fixed(c[k] * weight));
+        }
+        self.weights[p] = self.weights[p].wrapping_add(to_fixed(weight));
+    }
+
+    fn add(&mut self, other: &Splats) {
+        for (sum, value) in self.radiance.iter_mut().zip(&other.radiance) {
+            for k in 0..3 {
+                sum[k] = sum[k].wrapping_add(value[k]);
+            }
+        }
+        for (sum, &weight) in self.weights.iter_mut().zip(&other.weights) {
+            *sum = sum.wrapping_add(weight);
+        }
+    }
+
+    // The weighted average at p, or None if the weights don't add up to
+    // anything positive.
+    fn average(&self, p: usize) -> Option<Vec3> {
+        if self.weights[p] <= 0 {
+            return None;
+        }
+        let weight = self.weights[p] as f64;
+        let [r, g, b] = self.radiance[p].map(|sum| (sum as f64 / weight) as f32);
+        Some(Vec3::new(r, g, b))
+    }
+}
+
+// Accumulates samples over any number of passes, with rows spread over the
+// rayon thread pool. Lights are sampled explicitly at every non-specular hit
+// and combined with material sampling by multiple importance sampling.
+pub struct Renderer<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a [&'a dyn Hittable],
+    cam: &'a Camera,
+    settings: &'a RenderSettings,
+    // The AOVs being accumulated: those in settings plus any the denoiser
+    // needs.
+    aovs: Vec<Aov>,
+    rows: Vec<Row>,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(
+        world: &'a dyn Hittable,
+        lights: &'a [&'a dyn Hittable],
+        cam: &'a Camera,
+        settings: &'a RenderSettings,
+    ) -> Renderer<'a> {
+        let mut aovs = settings.aovs.clone();
+        if settings.denoise {
+            for &guide in &Denoiser::GUIDES {
+                if !aovs.contains(&guide) {
+                    aovs.push(guide);
+                }
+            }
+        }
+        let nx = settings.width as usize;
+        let row = Row {
+            radiance: vec![Vec3::default(); nx],
+            luminance_sq: vec![0.; nx],
+            samples: vec![0; nx],
+            filtered: Splats::new(nx),
+            aovs: vec![vec![Vec3::default(); nx]; aovs.len()],
+        };
+        Renderer {
+            world,
+            lights,
+            cam,
+            settings,
+            aovs,
+            rows: vec![row; settings.height as usize],
+        }
+    }
+
+    // The most samples any pixel has received. Without adaptive sampling
+    // every pixel has this many.
+    pub fn samples(&self) -> u32 {
+        self.rows
+            .iter()
+            .flat_map(|row| row.samples.iter().copied())
+            .max()
+            .unwrap_or(0)
+    }
+
+    // The number of pixels that still need samples.
+    pub fn active_pixels(&self) -> usize {
+        self.rows
+            .iter()
+            .map(|row| {
+                (0..row.samples.len())
+                    .filter(|&i| !self.is_done(row, i))
+                    .count()
+            })
+            .sum()
+    }
+
+    // Whether pixel i of row has had all the samples it needs. Convergence
+    // is checked before every sample rather than once per pass, so that it
+    // doesn't matter how samples are split into passes.
+    fn is_done(&self, row: &Row, i: usize) -> bool {
+        let n = row.samples[i];
+        if n >= self.settings.samples {
+            return true;
+        }
+        let threshold = match self.settings.adaptive_threshold {
+            Some(threshold) if n >= self.settings.min_samples.max(2) => threshold,
+            _ => return false,
+        };
+        let n = n as f32;
+        let mean = luminance(row.radiance[i]) / n;
+        let variance = ((row.luminance_sq[i] - mean * mean * n) / (n - 1.)).max(0.);
+        // Dark pixels are held to an absolute rather than relative error.
+        (variance / n).sqrt() <= threshold * mean.max(0.01)
+    }
+
+    // Adds up to ns samples to every pixel that isn't done.
+    pub fn render_pass(&mut self, ns: u32) {
+        let (nx, ny) = (self.settings.width, self.settings.height);
+        let (world, lights, cam) = (self.world, self.lights, self.cam);
+        let settings = self.settings;
+        let max_depth = settings.max_depth;
+        let aovs = &self.aovs;
+        let filter = &settings.filter;
+        // How many rows above and below its own a sample can reach.
+        let reach = (filter.radius() - 0.5).max(0.).ceil() as usize;
+        let mut rows = std::mem::take(&mut self.rows);
+
+        // Every sample's numbers depend only on the seed, the pixel and the
+        // index of the sample within the pixel. The image is then the same
+        // however samples are split into passes or spread over threads, and a
+        // resumed render matches one that was never interrupted.
+        //
+        // Samples that land in other rows are collected in a strip per row,
+        // covering the rows it reaches, and added once all rows are done.
+        let strips: Vec<Vec<Splats>> = rows
+            .par_iter_mut()
+            .enumerate()
+            .map(|(row, acc)| {
+                let mut sampler = settings.sampler.build(settings.seed, settings.samples);
+                let mut strip = vec![Splats::new(nx as usize); 2 * reach + 1];
+                let j = ny - row as u32 - 1; // Flip points vertically.
+                for i in 0..nx as usize {
+                    let pixel = row as u64 * nx as u64 + i as u64;
+                    for _ in 0..ns {
+                        if self.is_done(acc, i) {
+                            break;
+                        }
+                        sampler.start_pixel_sample(pixel, acc.samples[i]);
+                        let (du, dv) = sampler.get_2d();
+                        let u = (i as f32 + du) / nx as f32;
+                        let v = (j as f32 + dv) / ny as f32;
+                        let r = cam.get_ray(u, v, &mut *sampler);
+                        let c = color(&r, world, lights, max_depth, &mut *sampler);
+                        acc.radiance[i] += c;
+                        acc.luminance_sq[i] += luminance(c) * luminance(c);
+
+                        for (q, dy) in filter.footprint(j, dv, ny) {
+                            let target = ny as usize - 1 - q;
+                            let splats = if target == row {
+                                &mut acc.filtered
+                            } else {
+                                &mut strip[target + reach - row]
+                            };
+                            for (p, dx) in filter.footprint(i as u32, du, nx) {
+                                splats.splat(p, c, filter.weight(dx, dy));
+                            }
+                        }
+
+                        if !aovs.is_empty() {
+                            let rec = trace(world, &r);
+                            for (aov, layer) in aovs.iter().zip(&mut acc.aovs) {
+                                if *aov == Aov::Samples {
+                                    continue;
+                                } else if aov.is_filtered() {
+                                    layer[i] += aov.value(rec.as_ref());
+                                } else if acc.samples[i] == 0 {
+                                    layer[i] = aov.value(rec.as_ref());
+                                }
+                            }
+                        }
+                        acc.samples[i] += 1;
+                    }
+                }
+                strip
+            })
+            .collect();
+
+        if reach > 0 {
+            // Rows take their share of the strips in a fixed order, whatever
+            // order the strips were finished in.
+            rows.par_iter_mut().enumerate().for_each(|(row, acc)| {
+                let sources = row.saturating_sub(reach)..(row + reach + 1).min(strips.len());
+                for source in sources.filter(|&source| source != row) {
+                    acc.filtered.add(&strips[source][row + reach - source]);
+                }
+            });
+        }
+        self.rows = rows;
+    }
+
+    // The running sums so far, to be picked up later by resume.
+    pub fn checkpoint(&self) -> Checkpoint {
+        let rows = &self.rows;
+        Checkpoint {
+            width: self.settings.width,
+            height: self.settings.height,
+            max_depth: self.settings.max_depth,
+            sampler: self.settings.sampler,
+            pattern_samples: self.settings.samples,
+            seed: self.settings.seed,
+            aovs: self.aovs.clone(),
+            radiance: rows.iter().flat_map(|row| row.radiance.clone()).collect(),
+            luminance_sq: rows
+                .iter()
+                .flat_map(|row| row.luminance_sq.clone())
+                .collect(),
+            samples: rows.iter().flat_map(|row| row.samples.clone()).collect(),
+            filter: self.settings.filter,
+            filtered: rows
+                .iter()
+                .flat_map(|row| row.filtered.radiance.clone())
+                .collect(),
+            weights: rows
+                .iter()
+                .flat_map(|row| row.filtered.weights.clone())
+                .collect(),
+            aov_sums: (0..self.aovs.len())
+                .map(|k| rows.iter().flat_map(|row| row.aovs[k].clone()).collect())
+                .collect(),
+        }
+    }
+
+    // Replaces the samples so far with those from a checkpoint, which must
+    // have been taken with the same settings.
+    pub fn resume(&mut self, checkpoint: Checkpoint) -> Result<(), CheckpointError> {
+        let settings = self.settings;
+        if (checkpoint.width, checkpoint.height) != (settings.width, settings.height) {
+            return Err(CheckpointError::Mismatch(format!(
+                "image size is {}x{}, not {}x{}",
+                checkpoint.width, checkpoint.height, settings.width, settings.height
+            )));
+        }
+        if checkpoint.max_depth != settings.max_depth {
+            return Err(CheckpointError::Mismatch(format!(
+                "max depth is {}, not {}",
+                checkpoint.max_depth, settings.max_depth
+            )));
+        }
+        if checkpoint.seed != settings.seed {
+            return Err(CheckpointError::Mismatch(format!(
+                "seed is {}, not {}",
+                checkpoint.seed, settings.seed
+            )));
+        }
+        if checkpoint.sampler != settings.sampler {
+            return Err(CheckpointError::Mismatch(format!(
+                "sampler is {}, not {}",
+                checkpoint.sampler.name(),
+                settings.sampler.name()
+            )));
+        }
+        // The stratified sampler lays out its strata for the total number of
+        // samples.
+        if settings.sampler == SamplerKind::Stratified
+            && checkpoint.pattern_samples != settings.samples
+        {
+            return Err(CheckpointError::Mismatch(format!(
+                "stratified for {} samples, not {}",
+                checkpoint.pattern_samples, settings.samples
+            )));
+        }
+        if checkpoint.filter != settings.filter {
+            return Err(CheckpointError::Mismatch(format!(
+                "filter is {} of radius {}, not {} of radius {}",
+                checkpoint.filter.kind.name(),
+                checkpoint.filter.radius(),
+                settings.filter.kind.name(),
+                settings.filter.radius()
+            )));
+        }
+        if checkpoint.aovs != self.aovs {
+            let names = |aovs: &[Aov]| {
+                let names: Vec<_> = aovs.iter().map(|aov| aov.name()).collect();
+                format!("[{}]", names.join(", "))
+            };
+            return Err(CheckpointError::Mismatch(format!(
+                "AOVs are {}, not {}",
+                names(&checkpoint.aovs),
+                names(&self.aovs)
+            )));
+        }
+
+        let nx = settings.width as usize;
+        for (j, row) in self.rows.iter_mut().enumerate() {
+            let line = j * nx..(j + 1) * nx;
+            row.radiance
+                .copy_from_slice(&checkpoint.radiance[line.clone()]);
+            row.luminance_sq
+                .copy_from_slice(&checkpoint.luminance_sq[line.clone()]);
+            row.samples
+                .copy_from_slice(&checkpoint.samples[line.clone()]);
+            row.filtered
+                .radiance
+                .copy_from_slice(&checkpoint.filtered[line.clone()]);
+            row.filtered
+                .weights
+                .copy_from_slice(&checkpoint.weights[line.clone()]);
+            for (layer, sums) in row.aovs.iter_mut().zip(&checkpoint.aov_sums) {
+                layer.copy_from_slice(&sums[line.clone()]);
+            }
+        }
+        Ok(())
+    }
+
+    // Renders in passes of settings.pass_samples until either every pixel is
+    // done or settings.time_limit runs out, calling snapshot
+    // after every pass, or at most every settings.snapshot_interval seconds.
+    // The final image is returned rather than passed to snapshot.
+    pub fn run<F: FnMut(&Renderer, &Progress)>(&mut self, mut snapshot: F) -> Framebuffer {
+        let settings = self.settings;
+        let start = Instant::now();
+        let mut last_snapshot = start;
+        // With only a time limit, passes are kept short so as not to overrun
+        // it.
+        let pass = match (settings.pass_samples, settings.time_limit) {
+            (Some(pass), _) => pass.max(1),
+            (None, Some(_)) => 1,
+            (None, None) => settings.samples.max(1),
+        };
+
+        while self.active_pixels() > 0 {
+            self.render_pass(pass);
+
+            let progress = Progress {
+                samples: self.samples(),
+                active_pixels: self.active_pixels(),
+                elapsed: start.elapsed(),
+            };
+            let out_of_time = settings
+                .time_limit
+                .is_some_and(|limit| progress.elapsed.as_secs_f32() >= limit);
+            if out_of_time || progress.active_pixels == 0 {
+                break;
+            }
+            let due = settings
+                .snapshot_interval
+                .is_none_or(|interval| last_snapshot.elapsed().as_secs_f32() >= interval);
+            if due {
+                snapshot(self, &progress);
+                last_snapshot = Instant::now();
+            }
+        }
+        self.framebuffer()
+    }
+
+    // The average of the samples so far, with the AOVs asked for in settings,
+    // and denoised if settings ask for that.
+    pub fn framebuffer(&self) -> Framebuffer {
+        let nx = self.settings.width as usize;
+        let mut fb = Framebuffer::new(self.settings.width, self.settings.height);
+        let average = |sum: Vec3, n: u32| if n > 0 { sum / n as f32 } else { sum };
+
+        for (line, row) in fb.pixels_mut().chunks_mut(nx).zip(&self.rows) {
+            for (i, pixel) in line.iter_mut().enumerate() {
+                // Negative lobes can cancel out the weights of a pixel with
+                // few samples nearby, so fall back on its own samples.
+                *pixel = row
+                    .filtered
+                    .average(i)
+                    .unwrap_or_else(|| average(row.radiance[i], row.samples[i]));
+            }
+        }
+        for (k, &aov) in self.aovs.iter().enumerate() {
+            fb.add_aov(aov);
+            let layer = fb.aov_mut(aov).unwrap();
+            for (line, row) in layer.chunks_mut(nx).zip(&self.rows) {
+                for (i, value) in line.iter_mut().enumerate() {
+                    *value = if aov == Aov::Samples {
+                        let n = row.samples[i] as f32;
+                        Vec3::new(n, n, n)
+                    } else if aov.is_filtered() {
+                        average(row.aovs[k][i], row.samples[i])
+                    } else {
+                        row.aovs[k][i]
+                    };
+                }
+            }
+        }
+
+        if self.settings.denoise {
+            self.settings.denoiser.denoise(&mut fb);
+            for aov in &Denoiser::GUIDES {
+                if !self.settings.aovs.contains(aov) {
+                    fb.remove_aov(*aov);
+                }
+            }
+        }
+        fb
+    }
+}
+
+pub struct Progress {
+    // The most samples any pixel has so far.
+    pub samples: u32,
+    pub active_pixels: usize,
+    pub elapsed: Duration,
+}
+
+// Renders the averaged linear radiance of every pixel, and the AOVs asked for
+// in settings, in a single pass.
+pub fn render(
+    world: &dyn Hittable,
+    lights: &[&dyn Hittable],
+    cam: &Camera,
+    settings: &RenderSettings,
+) -> Framebuffer {
+    let mut renderer = Renderer::new(world, lights, cam, settings);
+    renderer.render_pass(settings.samples);
+    renderer.framebuffer()
+}
+
+// Renders progressively as Renderer::run does, passing snapshot the image so
+// far.
+pub fn render_progressive<F: FnMut(&Framebuffer, &Progress)>(
+    world: &dyn Hittable,
+    lights: &[&dyn Hittable],
+    cam: &Camera,
+    settings: &RenderSettings,
+    mut snapshot: F,
+) -> Framebuffer {
+    let mut renderer = Renderer::new(world, lights, cam, settings);
+    renderer.run(|renderer, progress| snapshot(&renderer.framebuffer(), progress))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::camera::CameraSettings;
+    use crate::filter::FilterKind;
+    use crate::material::Material;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+
+    fn bits(fb: &Framebuffer) -> Vec<u32> {
+        fb.pixels()
+            .iter()
+            .flat_map(|pixel| (0..3).map(move |k| pixel[k].to_bits()))
+            .collect()
+    }
+
+    // A render stopped after a few samples and resumed from its checkpoint
+    // gives exactly the image of one that ran straight through.
+    #[test]
+    fn resumed_render_matches_uninterrupted() {
+        let world: Vec<Box<dyn Hittable>> = vec![
+            Box::new(Sphere::new(
+                Vec3::new(0., 0., 0.),
+                1.,
+                Material::Diffuse(Texture::solid((0.7, 0.4, 0.2))),
+            )),
+            Box::new(Sphere::new(
+                Vec3::new(0., 3., 1.),
+                0.5,
+                Material::Light(Texture::solid((8., 8., 8.))),
+            )),
+        ];
+        let lights: Vec<&dyn Hittable> = vec![&*world[1]];
+        let cam = CameraSettings::new((0., 1., 6.), (0., 0., 0.), 40.).build(1.5);
+
+        for kind in [FilterKind::Box, FilterKind::Mitchell, FilterKind::Gaussian] {
+            let settings = RenderSettings {
+                width: 24,
+                height: 16,
+                samples: 8,
+                max_depth: 4,
+                filter: Filter { kind, radius: None },
+                ..RenderSettings::default()
+            };
+
+            let mut straight = Renderer::new(&world, &lights, &cam, &settings);
+            straight.render_pass(settings.samples);
+
+            let mut interrupted = Renderer::new(&world, &lights, &cam, &settings);
+            interrupted.render_pass(3);
+            let mut resumed = Renderer::new(&world, &lights, &cam, &settings);
+            resumed.resume(interrupted.checkpoint()).unwrap();
+            resumed.render_pass(2);
+            resumed.render_pass(settings.samples);
+
+            assert!(
+                bits(&straight.framebuffer()) == bits(&resumed.framebuffer()),
+                "{} filter",
+                kind.name()
+            );
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct RotateY {
@@ -16,8 +17,8 @@ impl RotateY {
         let sin_theta = radians.sin();
         let cos_theta = radians.cos();
         let bbox = hittable.bounding_box(0., 1.).map(|bbox| {
-            let mut min = Vec3::new(std::f32::MAX, std::f32::MAX, std::f32::MAX);
-            let mut max = Vec3::new(std::f32::MIN, std::f32::MIN, std::f32::MIN);
+            let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+            let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
             for i in 0..2 {
                 for j in 0..2 {
                     for k in 0..2 {
@@ -44,30 +45,54 @@ impl RotateY {
             bbox,
         }
     }
+
+    fn to_object(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] - self.sin_theta * v[2],
+            v[1],
+            self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
+
+    fn to_world(&self, v: Vec3) -> Vec3 {
+        Vec3::new(
+            self.cos_theta * v[0] + self.sin_theta * v[2],
+            v[1],
+            -self.sin_theta * v[0] + self.cos_theta * v[2],
+        )
+    }
 }
 
 impl Hittable for RotateY {
     fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
-        let mut origin = r.origin();
-        let mut direction = r.direction();
-        origin[0] = self.cos_theta * r.origin()[0] - self.sin_theta * r.origin()[2];
-        origin[2] = self.sin_theta * r.origin()[0] + self.cos_theta * r.origin()[2];
-        direction[0] = self.cos_theta * r.direction()[0] - self.sin_theta * r.direction()[2];
-        direction[2] = self.sin_theta * r.direction()[0] + self.cos_theta * r.direction()[2];
-
-        let rotated_r = Ray::new(origin, direction, r.time());
-        self.hittable.hit(&rotated_r, t_min, t_max).map(|rec| {
-            let mut p = rec.p;
-            let mut normal = rec.normal;
-            p[0] = self.cos_theta * rec.p[0] + self.sin_theta * rec.p[2];
-            p[2] = -self.sin_theta * rec.p[0] + self.cos_theta * rec.p[2];
-            normal[0] = self.cos_theta * rec.normal[0] + self.sin_theta * rec.normal[2];
-            normal[2] = -self.sin_theta * rec.normal[0] + self.cos_theta * rec.normal[2];
-            HitRecord { p, normal, ..rec }
-        })
+        let rotated_r = Ray::new(
+            self.to_object(r.origin()),
+            self.to_object(r.direction()),
+            r.time(),
+        );
+        self.hittable
+            .hit(&rotated_r, t_min, t_max)
+            .map(|rec| HitRecord {
+                p: self.to_world(rec.p),
+                normal: self.to_world(rec.normal),
+                ..rec
+            })
     }
 
     fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
         self.bbox.clone()
     }
+
+    fn is_emissive(&self) -> bool {
+        self.hittable.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        self.hittable
+            .pdf_value(self.to_object(o), self.to_object(v), time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        self.to_world(self.hittable.random(self.to_object(o), time, sampler))
+    }
 }
@@ -0,0 +1,451 @@
+use lazy_static::lazy_static;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+use serde::{Deserialize, Serialize};
+
+// Supplies the uniform numbers in [0, 1) that a sample of a pixel turns into
+// a camera ray, light samples and scattering directions. Each call consumes
+// the next dimension of the sample, so a sampler can spread the values of any
+// one dimension evenly over the samples of a pixel.
+pub trait Sampler: Send {
+    // Begins sample number index of pixel, with the dimensions that follow
+    // numbered from 0 again.
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32);
+    fn get_1d(&mut self) -> f32;
+    fn get_2d(&mut self) -> (f32, f32);
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SamplerKind {
+    // Independent random numbers.
+    Independent,
+    // Jittered strata, laid out for a fixed number of samples per pixel.
+    Stratified,
+    // The Halton sequence with random digit scrambling.
+    Halton,
+    // Owen-scrambled Sobol points.
+    Sobol,
+}
+
+impl SamplerKind {
+    pub fn name(self) -> &'static str {
+        match self {
+            SamplerKind::Independent => "independent",
+            SamplerKind::Stratified => "stratified",
+            SamplerKind::Halton => "halton",
+            SamplerKind::Sobol => "sobol",
+        }
+    }
+
+    // A sampler whose values are determined by seed and the pixel, sample
+    // and dimension they are for. samples_per_pixel only matters to the
+    // stratified sampler.
+    pub fn build(self, seed: u64, samples_per_pixel: u32) -> Box<dyn Sampler> {
+        let state = SampleState::new(seed);
+        match self {
+            SamplerKind::Independent => Box::new(IndependentSampler {
+                rng: Pcg32::seed_from_u64(seed),
+                state,
+            }),
+            SamplerKind::Stratified => Box::new(StratifiedSampler {
+                samples_per_pixel: samples_per_pixel.max(1),
+                state,
+            }),
+            SamplerKind::Halton => Box::new(HaltonSampler { state }),
+            SamplerKind::Sobol => Box::new(SobolSampler { state }),
+        }
+    }
+}
+
+// SplitMix64's finalizer, for turning structured seeds into well mixed ones.
+pub fn mix(mut x: u64) -> u64 {
+    x = x.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    x ^ (x >> 31)
+}
+
+// A float in [0, 1) from the high bits of a hash.
+pub fn hash_to_float(h: u64) -> f32 {
+    (h >> 40) as f32 / (1u32 << 24) as f32
+}
+
+// The largest float below 1.
+const ONE_MINUS_EPSILON: f32 = 1. - f32::EPSILON / 2.;
+
+// Where a sampler is: which pixel, which sample of it and which dimension.
+struct SampleState {
+    seed: u64,
+    pixel: u64,
+    index: u32,
+    dimension: u32,
+}
+
+impl SampleState {
+    fn new(seed: u64) -> SampleState {
+        SampleState {
+            seed,
+            pixel: 0,
+            index: 0,
+            dimension: 0,
+        }
+    }
+
+    fn start(&mut self, pixel: u64, index: u32) {
+        self.pixel = pixel;
+        self.index = index;
+        self.dimension = 0;
+    }
+
+    // Hash of the pixel and the next dimension, shared by every sample of the
+    // pixel so that the samples can be spread out within that dimension.
+    fn next_dimension(&mut self) -> u64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        mix(mix(mix(self.seed) ^ self.pixel) ^ dimension as u64)
+    }
+
+    // A value for this particular sample, for when a pattern runs out.
+    fn random(&self, dimension_hash: u64, salt: u64) -> f32 {
+        hash_to_float(mix(dimension_hash ^ mix(self.index as u64) ^ salt))
+    }
+}
+
+struct IndependentSampler {
+    rng: Pcg32,
+    state: SampleState,
+}
+
+impl Sampler for IndependentSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+        let seed = mix(mix(mix(self.state.seed) ^ pixel) ^ index as u64);
+        self.rng = Pcg32::seed_from_u64(seed);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.rng.gen()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.rng.gen(), self.rng.gen())
+    }
+}
+
+// Kensler's hash-based permutation of 0..len, keyed by key. Values that land
+// outside the range are hashed again until they fall inside it.
+fn permute(mut i: u32, len: u32, key: u32) -> u32 {
+    let mut w = len - 1;
+    w |= w >> 1;
+    w |= w >> 2;
+    w |= w >> 4;
+    w |= w >> 8;
+    w |= w >> 16;
+    loop {
+        i ^= key;
+        i = i.wrapping_mul(0xe170_893d);
+        i ^= key >> 16;
+        i ^= (i & w) >> 4;
+        i ^= key >> 8;
+        i = i.wrapping_mul(0x0929_eb3f);
+        i ^= key >> 23;
+        i ^= (i & w) >> 1;
+        i = i.wrapping_mul(1 | key >> 27);
+        i = i.wrapping_mul(0x6935_fa69);
+        i ^= (i & w) >> 11;
+        i = i.wrapping_mul(0x74dc_b303);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0x9e50_1cc3);
+        i ^= (i & w) >> 2;
+        i = i.wrapping_mul(0xc860_a3df);
+        i &= w;
+        i ^= i >> 5;
+        if i < len {
+            return (i.wrapping_add(key)) % len;
+        }
+    }
+}
+
+// Puts every sample of a pixel in its own stratum of each dimension, with the
+// strata shuffled differently for every dimension. 2D values use a square
+// grid of strata. Samples beyond the layout are independent.
+struct StratifiedSampler {
+    samples_per_pixel: u32,
+    state: SampleState,
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        let h = self.state.next_dimension();
+        let n = self.samples_per_pixel;
+        let jitter = self.state.random(h, 0);
+        if self.state.index >= n {
+            return jitter;
+        }
+        let stratum = permute(self.state.index, n, h as u32);
+        ((stratum as f32 + jitter) / n as f32).min(ONE_MINUS_EPSILON)
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        let h = self.state.next_dimension();
+        let n = (self.samples_per_pixel as f32).sqrt().ceil() as u32;
+        let (jx, jy) = (self.state.random(h, 0), self.state.random(h, 1));
+        if self.state.index >= n * n {
+            return (jx, jy);
+        }
+        let stratum = permute(self.state.index, n * n, h as u32);
+        let (sx, sy) = (stratum % n, stratum / n);
+        (
+            ((sx as f32 + jx) / n as f32).min(ONE_MINUS_EPSILON),
+            ((sy as f32 + jy) / n as f32).min(ONE_MINUS_EPSILON),
+        )
+    }
+}
+
+// Bases for the Halton dimensions; later dimensions are independent.
+const HALTON_DIMENSIONS: usize = 256;
+
+lazy_static! {
+    static ref PRIMES: Vec<u64> = {
+        let mut primes = Vec::with_capacity(HALTON_DIMENSIONS);
+        let mut n = 2;
+        while primes.len() < HALTON_DIMENSIONS {
+            if primes.iter().all(|p| n % p != 0) {
+                primes.push(n);
+            }
+            n += 1;
+        }
+        primes
+    };
+}
+
+// The radical inverse of index in base, with every digit shifted by an amount
+// that depends on its position and on seed. Digits continue past the last
+// nonzero one, so the result isn't limited to multiples of the resolution
+// that index alone gives.
+fn scrambled_radical_inverse(base: u64, mut index: u64, seed: u64) -> f32 {
+    let inv_base = 1. / base as f64;
+    let mut scale = inv_base;
+    let mut result = 0.;
+    let mut level = 0;
+    while scale > 1e-9 {
+        let shift = mix(seed ^ level) % base;
+        let digit = (index % base + shift) % base;
+        result += digit as f64 * scale;
+        index /= base;
+        scale *= inv_base;
+        level += 1;
+    }
+    (result as f32).min(ONE_MINUS_EPSILON)
+}
+
+// Dimension d takes the Halton sequence in the d-th prime base, indexed by
+// the sample number and scrambled per pixel.
+struct HaltonSampler {
+    state: SampleState,
+}
+
+impl HaltonSampler {
+    fn next(&mut self) -> f32 {
+        let dimension = self.state.dimension as usize;
+        let h = self.state.next_dimension();
+        match PRIMES.get(dimension) {
+            Some(&base) => scrambled_radical_inverse(base, self.state.index as u64, h),
+            None => self.state.random(h, 0),
+        }
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next()
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        (self.next(), self.next())
+    }
+}
+
+// Hash-based Owen scrambling (Burley 2020): randomly flipping the bits of a
+// reversed value is a nested uniform scramble of the original.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50_b47c);
+    x ^= x.wrapping_mul(0xb82f_1e52);
+    x ^= x.wrapping_mul(0xc7af_e638);
+    x ^= x.wrapping_mul(0x8d22_f6e6);
+    x.reverse_bits()
+}
+
+// The first two dimensions of the Sobol sequence, as 32-bit fractions.
+fn sobol_2d(index: u32) -> (u32, u32) {
+    let x = index.reverse_bits();
+    let mut y = 0;
+    let mut v: u32 = 1 << 31;
+    let mut i = index;
+    while i != 0 {
+        if i & 1 != 0 {
+            y ^= v;
+        }
+        v ^= v >> 1;
+        i >>= 1;
+    }
+    (x, y)
+}
+
+fn fraction(x: u32) -> f32 {
+    (x >> 8) as f32 / (1u32 << 24) as f32
+}
+
+// Every 1D or 2D request is a separately shuffled and Owen-scrambled copy of
+// the 2D Sobol sequence, which keeps its stratification in every pair of
+// dimensions without needing direction numbers for higher ones.
+struct SobolSampler {
+    state: SampleState,
+}
+
+impl SobolSampler {
+    fn next(&mut self) -> (f32, f32) {
+        let h = self.state.next_dimension();
+        let index = nested_uniform_scramble(self.state.index, h as u32);
+        let (x, y) = sobol_2d(index);
+        (
+            fraction(nested_uniform_scramble(x, (h >> 32) as u32)),
+            fraction(nested_uniform_scramble(y, mix(h) as u32)),
+        )
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_pixel_sample(&mut self, pixel: u64, index: u32) {
+        self.state.start(pixel, index);
+    }
+
+    fn get_1d(&mut self) -> f32 {
+        self.next().0
+    }
+
+    fn get_2d(&mut self) -> (f32, f32) {
+        self.next()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [
+        SamplerKind::Independent,
+        SamplerKind::Stratified,
+        SamplerKind::Halton,
+        SamplerKind::Sobol,
+    ];
+
+    // The values of dimension of samples 0..n of pixel, taking the 1D or 2D
+    // values of every dimension before it.
+    fn dimension(sampler: &mut dyn Sampler, pixel: u64, n: u32, dim: usize) -> Vec<(f32, f32)> {
+        (0..n)
+            .map(|index| {
+                sampler.start_pixel_sample(pixel, index);
+                for d in 0..dim {
+                    if d.is_multiple_of(2) {
+                        sampler.get_1d();
+                    } else {
+                        sampler.get_2d();
+                    }
+                }
+                if dim.is_multiple_of(2) {
+                    (sampler.get_1d(), 0.)
+                } else {
+                    sampler.get_2d()
+                }
+            })
+            .collect()
+    }
+
+    // Whether each of the n strata of [0, 1) has one value.
+    fn stratified(values: impl Iterator<Item = f32>, n: u32) -> bool {
+        let mut strata: Vec<_> = values.map(|x| (x * n as f32) as u32).collect();
+        strata.sort_unstable();
+        strata == (0..n).collect::<Vec<_>>()
+    }
+
+    #[test]
+    fn values_are_in_unit_interval() {
+        for kind in KINDS {
+            // More samples than the stratified layout and more dimensions
+            // than Halton has bases, to cover the fallbacks too.
+            let mut sampler = kind.build(3, 16);
+            for pixel in 0..8 {
+                for index in 0..40 {
+                    sampler.start_pixel_sample(pixel, index);
+                    for _ in 0..(HALTON_DIMENSIONS + 4) {
+                        let x = sampler.get_1d();
+                        let (u, v) = sampler.get_2d();
+                        for value in [x, u, v] {
+                            assert!((0. ..1.).contains(&value), "{}: {}", kind.name(), value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn values_depend_only_on_pixel_sample_and_dimension() {
+        for kind in KINDS {
+            let mut a = kind.build(5, 16);
+            let mut b = kind.build(5, 16);
+            // Leave b somewhere else first.
+            b.start_pixel_sample(9, 3);
+            b.get_2d();
+            assert_eq!(
+                dimension(&mut *a, 4, 16, 3),
+                dimension(&mut *b, 4, 16, 3),
+                "{}",
+                kind.name()
+            );
+        }
+    }
+
+    #[test]
+    fn samples_are_stratified() {
+        let n = 16;
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.build(1, n);
+            for pixel in 0..4 {
+                for dim in 0..6 {
+                    let values = dimension(&mut *sampler, pixel, n, dim);
+                    let ok = if dim.is_multiple_of(2) {
+                        stratified(values.iter().map(|v| v.0), n)
+                    } else {
+                        // One sample in each cell of a 4x4 grid.
+                        let cells = values.iter().map(|&(u, v)| {
+                            ((u * 4.) as u32 * 4 + (v * 4.) as u32) as f32 / n as f32
+                        });
+                        stratified(cells, n)
+                    };
+                    assert!(ok, "{} dimension {}: {:?}", kind.name(), dim, values);
+                }
+            }
+        }
+
+        // Base 2 and base 3 Halton points are stratified over powers of the
+        // base.
+        let mut sampler = SamplerKind::Halton.build(1, 0);
+        let values = dimension(&mut *sampler, 0, 16, 0);
+        assert!(stratified(values.iter().map(|v| v.0), 16));
+        let values = dimension(&mut *sampler, 0, 9, 1);
+        assert!(stratified(values.iter().map(|v| v.0), 9));
+    }
+}
@@ -0,0 +1,975 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use serde::Deserialize;
+use toml::Spanned;
+
+use crate::animated_transform::{AnimatedTransform, Keyframe};
+use crate::bvh::{SplitMethod, BVH};
+use crate::camera::CameraSettings;
+use crate::constant_medium::ConstantMedium;
+use crate::cornellbox::CornellBox;
+use crate::heterogeneous_medium::{DensityField, HeterogeneousMedium};
+use crate::hittable::{flip_normals, Hittable};
+use crate::instance::Instance;
+use crate::mat4::Mat4;
+use crate::material::Material;
+use crate::moving_sphere::MovingSphere;
+use crate::obj::{load_obj, ObjError};
+use crate::quaternion::Quat;
+use crate::rectangle::{XYRect, XZRect, YZRect};
+use crate::render::RenderSettings;
+use crate::rotate::RotateY;
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::tonemap::ToneMapper;
+use crate::transform::Transform;
+use crate::translate::Translate;
+use crate::triangle::TriangleMesh;
+use crate::vec3::Vec3;
+use crate::voxel_grid::{GridError, VoxelGrid};
+
+pub struct Scene {
+    pub world: Vec<Box<dyn Hittable>>,
+    pub camera: CameraSettings,
+    pub render: RenderSettings,
+    pub tonemap: ToneMapper,
+}
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        msg: String,
+    },
+    // The scene has no objects, which leaves nothing to build a BVH over.
+    Empty {
+        path: PathBuf,
+    },
+    Image {
+        path: PathBuf,
+        err: image::ImageError,
+    },
+    Obj(ObjError),
+    Grid(GridError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SceneError::Io { path, err } => write!(f, "{}: {}", path.display(), err),
+            SceneError::Parse { path, line, msg } => {
+                write!(f, "{}:{}: {}", path.display(), line, msg)
+            }
+            SceneError::Empty { path } => write!(f, "{}: scene has no objects", path.display()),
+            SceneError::Image { path, err } => write!(f, "{}: {}", path.display(), err),
+            SceneError::Obj(err) => err.fmt(f),
+            SceneError::Grid(err) => err.fmt(f),
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io { err, .. } => Some(err),
+            SceneError::Parse { .. } | SceneError::Empty { .. } => None,
+            SceneError::Image { err, .. } => Some(err),
+            SceneError::Obj(err) => Some(err),
+            SceneError::Grid(err) => Some(err),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(err: ObjError) -> Self {
+        SceneError::Obj(err)
+    }
+}
+
+impl From<GridError> for SceneError {
+    fn from(err: GridError) -> Self {
+        SceneError::Grid(err)
+    }
+}
+
+// The on-disk scene format. Textures and materials are named tables that
+// objects refer to, e.g.
+//
+//     [materials.white]
+//     type = "diffuse"
+//     color = [0.73, 0.73, 0.73]
+//
+//     [[objects]]
+//     type = "box"
+//     p0 = [0, 0, 0]
+//     p1 = [165, 330, 165]
+//     material = "white"
+//     transforms = [{ rotate_y = 15 }, { translate = [265, 0, 295] }]
+//
+// Besides translate and rotate_y, transforms can be rotate_x, rotate_z, scale
+// by a number or a factor per axis, matrix, the top three rows of any affine
+// matrix, or keyframes, which move the object over the shutter interval.
+//
+// Geometry that appears many times is defined once as a named list of
+// objects and placed by instances, each of which can override its materials:
+//
+//     [[geometries.post]]
+//     type = "box"
+//     p0 = [0, 0, 0]
+//     p1 = [10, 100, 10]
+//     material = "white"
+//
+//     [[objects]]
+//     type = "instance"
+//     geometry = "post"
+//     material = "red"
+//     transforms = [{ translate = [100, 0, 50] }]
+//
+// See scenes/cornell_box.toml for a complete example.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    camera: CameraSettings,
+    render: Option<Spanned<RenderSettings>>,
+    #[serde(default)]
+    tonemap: ToneMapper,
+    #[serde(default)]
+    textures: HashMap<String, Spanned<TextureDesc>>,
+    #[serde(default)]
+    materials: HashMap<String, Spanned<MaterialDesc>>,
+    // Named lists of objects that instances share, each built once.
+    #[serde(default)]
+    geometries: HashMap<String, Vec<Spanned<ObjectDesc>>>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    Solid { color: Vec3 },
+    Checker { odd: String, even: String },
+    Noise { scale: f32 },
+    Image { path: PathBuf },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDesc {
+    Diffuse {
+        color: Option<Vec3>,
+        texture: Option<String>,
+    },
+    Light {
+        color: Option<Vec3>,
+        texture: Option<String>,
+    },
+    Isotropic {
+        color: Option<Vec3>,
+        texture: Option<String>,
+    },
+    Metal {
+        albedo: Vec3,
+        #[serde(default)]
+        fuzz: f32,
+    },
+    Glass {
+        ref_idx: f32,
+    },
+}
+
+#[derive(Deserialize)]
+struct ObjectDesc {
+    #[serde(flatten)]
+    shape: ShapeDesc,
+    material: Option<String>,
+    #[serde(default)]
+    flip_normals: bool,
+    // Fills the shape with a volume of this density that scatters with the
+    // material, instead of making it a surface.
+    density: Option<f32>,
+    // Fills the shape with a volume of varying density instead, which needs
+    // no material.
+    medium: Option<MediumDesc>,
+    // Applied in order, so the first transform is innermost.
+    #[serde(default)]
+    transforms: Vec<TransformDesc>,
+}
+
+// Flattened into ObjectDesc, which hands on only the keys it doesn't know
+// itself, so any misspelled key of an object is rejected here.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDesc {
+    Sphere {
+        center: Vec3,
+        radius: f32,
+    },
+    MovingSphere {
+        center0: Vec3,
+        center1: Vec3,
+        time0: f32,
+        time1: f32,
+        radius: f32,
+    },
+    XyRect {
+        x0: f32,
+        x1: f32,
+        y0: f32,
+        y1: f32,
+        k: f32,
+    },
+    XzRect {
+        x0: f32,
+        x1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+    },
+    YzRect {
+        y0: f32,
+        y1: f32,
+        z0: f32,
+        z1: f32,
+        k: f32,
+    },
+    Box {
+        p0: Vec3,
+        p1: Vec3,
+    },
+    Triangle {
+        vertices: [Vec3; 3],
+    },
+    Obj {
+        path: PathBuf,
+    },
+    // A placement of one of the scene's geometries. Its material, if any,
+    // replaces all of the geometry's own.
+    Instance {
+        geometry: String,
+    },
+}
+
+// A heterogeneous medium, with coefficients per unit length at full density
+// and a field giving the fraction of that density at each point, e.g.
+//
+//     medium = { scattering = [0.05, 0.05, 0.05], field = "noise", frequency = 0.02 }
+//     medium = { absorption = [0.02, 0.02, 0.02], field = "grid", path = "cloud.txt",
+//                min = [0, 0, 0], max = [100, 100, 100] }
+#[derive(Deserialize)]
+struct MediumDesc {
+    #[serde(default)]
+    absorption: Vec3,
+    #[serde(default)]
+    scattering: Vec3,
+    #[serde(flatten)]
+    field: FieldDesc,
+}
+
+// Flattened into MediumDesc, so likewise rejects its leftover keys.
+#[derive(Deserialize)]
+#[serde(tag = "field", rename_all = "snake_case", deny_unknown_fields)]
+enum FieldDesc {
+    Noise { frequency: f32 },
+    Grid { path: PathBuf, min: Vec3, max: Vec3 },
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+enum TransformDesc {
+    Translate(Vec3),
+    RotateX(f32),
+    RotateY(f32),
+    RotateZ(f32),
+    Scale(ScaleDesc),
+    // The top three rows of an affine matrix.
+    Matrix([[f32; 4]; 3]),
+    Keyframes(Vec<KeyframeDesc>),
+}
+
+// A factor for every axis or one for all of them.
+#[derive(Clone, Copy, Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f32),
+    Axes(Vec3),
+}
+
+impl TransformDesc {
+    // The transform as a matrix, unless it is animated.
+    fn matrix(&self) -> Option<Mat4> {
+        Some(match self {
+            TransformDesc::Translate(offset) => Mat4::translate(*offset),
+            TransformDesc::RotateX(angle) => Mat4::rotate_x(*angle),
+            TransformDesc::RotateY(angle) => Mat4::rotate_y(*angle),
+            TransformDesc::RotateZ(angle) => Mat4::rotate_z(*angle),
+            TransformDesc::Scale(scale) => Mat4::scale(scale.factors()),
+            TransformDesc::Matrix(rows) => Mat4::affine(*rows),
+            TransformDesc::Keyframes(_) => return None,
+        })
+    }
+}
+
+impl ScaleDesc {
+    fn factors(self) -> Vec3 {
+        match self {
+            ScaleDesc::Uniform(s) => Vec3::new(s, s, s),
+            ScaleDesc::Axes(factors) => factors,
+        }
+    }
+}
+
+// Where an animated object is at time, scaled, then rotated by angle degrees
+// about axis, then translated, e.g.
+//
+//     transforms = [{ keyframes = [
+//         { time = 0, translate = [0, 0, 0] },
+//         { time = 1, translate = [0, 50, 0], rotate = { axis = [0, 1, 0], angle = 90 } },
+//     ] }]
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct KeyframeDesc {
+    time: f32,
+    #[serde(default)]
+    translate: Vec3,
+    rotate: Option<RotationDesc>,
+    scale: Option<ScaleDesc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RotationDesc {
+    axis: Vec3,
+    angle: f32,
+}
+
+impl Scene {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scene, SceneError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|err| SceneError::Io {
+            path: path.to_owned(),
+            err,
+        })?;
+        let loader = Loader {
+            path,
+            dir: path.parent().unwrap_or_else(|| Path::new("")),
+            source: &source,
+        };
+
+        let desc: SceneDesc = toml::from_str(&source).map_err(|err| {
+            let offset = err.span().map_or(0, |span| span.start);
+            loader.error(offset, err.message().to_string())
+        })?;
+        loader.build(desc)
+    }
+}
+
+struct Loader<'a> {
+    path: &'a Path,
+    dir: &'a Path,
+    source: &'a str,
+}
+
+// What objects are built with: the scene's materials, its shared geometry and
+// the shutter interval.
+struct Context<'d> {
+    materials: HashMap<&'d str, Material>,
+    geometries: &'d HashMap<String, Vec<Spanned<ObjectDesc>>>,
+    built: HashMap<&'d str, Arc<dyn Hittable>>,
+    // The geometry being built, innermost last, to catch any that contains
+    // itself.
+    building: Vec<&'d str>,
+    time0: f32,
+    time1: f32,
+}
+
+impl<'a> Loader<'a> {
+    fn error(&self, offset: usize, msg: String) -> SceneError {
+        let line = self.source[..offset.min(self.source.len())]
+            .matches('\n')
+            .count()
+            + 1;
+        SceneError::Parse {
+            path: self.path.to_owned(),
+            line,
+            msg,
+        }
+    }
+
+    fn build(&self, desc: SceneDesc) -> Result<Scene, SceneError> {
+        if desc.objects.is_empty() {
+            return Err(SceneError::Empty {
+                path: self.path.to_owned(),
+            });
+        }
+
+        let render = match desc.render {
+            Some(render) => {
+                if render.get_ref().width == 0 || render.get_ref().height == 0 {
+                    return Err(self.error(
+                        render.span().start,
+                        "image width and height must be positive".into(),
+                    ));
+                }
+                render.into_inner()
+            }
+            None => RenderSettings::default(),
+        };
+
+        let mut materials = HashMap::new();
+        for (name, mat) in &desc.materials {
+            materials.insert(name.as_str(), self.material(mat, &desc.textures)?);
+        }
+
+        let mut ctx = Context {
+            materials,
+            geometries: &desc.geometries,
+            built: HashMap::new(),
+            building: Vec::new(),
+            time0: desc.camera.time0,
+            time1: desc.camera.time1,
+        };
+        let world = self.objects(&desc.objects, &mut ctx)?;
+
+        Ok(Scene {
+            world,
+            camera: desc.camera,
+            render,
+            tonemap: desc.tonemap,
+        })
+    }
+
+    fn texture(
+        &self,
+        name: &str,
+        offset: usize,
+        textures: &HashMap<String, Spanned<TextureDesc>>,
+        depth: usize,
+    ) -> Result<Texture, SceneError> {
+        let desc = match textures.get(name) {
+            Some(desc) => desc,
+            None => return Err(self.error(offset, format!("undefined texture `{}`", name))),
+        };
+        let offset = desc.span().start;
+        // Every texture can only be nested once per path from the root, so
+        // anything deeper than that is a cycle.
+        if depth > textures.len() {
+            return Err(self.error(offset, format!("texture `{}` refers to itself", name)));
+        }
+
+        Ok(match desc.get_ref() {
+            TextureDesc::Solid { color } => Texture::solid(*color),
+            TextureDesc::Checker { odd, even } => Texture::checker(
+                self.texture(odd, offset, textures, depth + 1)?,
+                self.texture(even, offset, textures, depth + 1)?,
+            ),
+            TextureDesc::Noise { scale } => Texture::noise(*scale),
+            TextureDesc::Image { path } => {
+                let path = self.dir.join(path);
+                Texture::image(&path).map_err(|err| SceneError::Image { path, err })?
+            }
+        })
+    }
+
+    fn material(
+        &self,
+        desc: &Spanned<MaterialDesc>,
+        textures: &HashMap<String, Spanned<TextureDesc>>,
+    ) -> Result<Material, SceneError> {
+        let offset = desc.span().start;
+        let texture = |color: &Option<Vec3>, texture: &Option<String>| match (color, texture) {
+            (Some(color), None) => Ok(Texture::solid(*color)),
+            (None, Some(name)) => self.texture(name, offset, textures, 0),
+            _ => Err(self.error(offset, "expected one of `color` or `texture`".into())),
+        };
+
+        Ok(match desc.get_ref() {
+            MaterialDesc::Diffuse { color, texture: t } => Material::Diffuse(texture(color, t)?),
+            MaterialDesc::Light { color, texture: t } => Material::Light(texture(color, t)?),
+            MaterialDesc::Isotropic { color, texture: t } => {
+                Material::Isotropic(texture(color, t)?)
+            }
+            MaterialDesc::Metal { albedo, fuzz } => Material::Metal(*albedo, *fuzz),
+            MaterialDesc::Glass { ref_idx } => Material::Glass(*ref_idx),
+        })
+    }
+
+    fn objects<'d>(
+        &self,
+        descs: &'d [Spanned<ObjectDesc>],
+        ctx: &mut Context<'d>,
+    ) -> Result<Vec<Box<dyn Hittable>>, SceneError> {
+        let mut objects = Vec::with_capacity(descs.len());
+        for object in descs {
+            let offset = object.span().start;
+            let mat = match &object.get_ref().material {
+                Some(name) => match ctx.materials.get(name.as_str()) {
+                    Some(mat) => Some(mat.clone()),
+                    None => {
+                        return Err(self.error(offset, format!("undefined material `{}`", name)))
+                    }
+                },
+                None => None,
+            };
+            objects.push(self.object(object.get_ref(), mat, offset, ctx)?);
+        }
+        Ok(objects)
+    }
+
+    // The named geometry, built the first time it is asked for.
+    fn geometry<'d>(
+        &self,
+        name: &str,
+        offset: usize,
+        ctx: &mut Context<'d>,
+    ) -> Result<Arc<dyn Hittable>, SceneError> {
+        let (name, descs) = match ctx.geometries.get_key_value(name) {
+            Some((name, descs)) => (name.as_str(), descs),
+            None => return Err(self.error(offset, format!("undefined geometry `{}`", name))),
+        };
+        if let Some(geometry) = ctx.built.get(name) {
+            return Ok(geometry.clone());
+        }
+        if ctx.building.contains(&name) {
+            return Err(self.error(offset, format!("geometry `{}` contains itself", name)));
+        }
+        if descs.is_empty() {
+            return Err(self.error(offset, format!("geometry `{}` is empty", name)));
+        }
+
+        ctx.building.push(name);
+        let mut objects = self.objects(descs, ctx)?;
+        ctx.building.pop();
+        let geometry: Arc<dyn Hittable> = Arc::new(BVH::new(&mut objects, ctx.time0, ctx.time1));
+        ctx.built.insert(name, geometry.clone());
+        Ok(geometry)
+    }
+
+    fn object<'d>(
+        &self,
+        desc: &'d ObjectDesc,
+        mat: Option<Material>,
+        offset: usize,
+        ctx: &mut Context<'d>,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        let phase_function = mat.clone();
+        // The boundary of a heterogeneous medium is never seen, so it can do
+        // without a material.
+        let mat = match (&desc.medium, mat) {
+            (Some(_), Some(_)) => {
+                return Err(self.error(offset, "media don't take a material".into()))
+            }
+            (Some(_), None) => Some(Material::Isotropic(Texture::solid((0., 0., 0.)))),
+            (None, mat) => mat,
+        };
+        let mut transforms = &desc.transforms[..];
+        let mut hittable: Box<dyn Hittable> = match (&desc.shape, mat) {
+            (ShapeDesc::Instance { geometry }, mat) => {
+                let geometry = self.geometry(geometry, offset, ctx)?;
+                // Transforms up to any keyframes are folded into the
+                // instance's own matrix.
+                let matrices: Vec<Mat4> =
+                    transforms.iter().map_while(TransformDesc::matrix).collect();
+                transforms = &transforms[matrices.len()..];
+                let matrix = matrices.into_iter().fold(Mat4::identity(), |m, t| t * m);
+                if matrix.inverse().is_none() {
+                    return Err(self.error(offset, "transform is not invertible".into()));
+                }
+                Box::new(Instance::new(geometry, matrix, mat))
+            }
+            (ShapeDesc::Obj { path }, None) => {
+                let mut triangles = load_obj(self.dir.join(path))?;
+                if triangles.is_empty() {
+                    return Err(self.error(offset, format!("`{}` has no faces", path.display())));
+                }
+                Box::new(BVH::with_split(
+                    &mut triangles,
+                    ctx.time0,
+                    ctx.time1,
+                    SplitMethod::Sah,
+                ))
+            }
+            (ShapeDesc::Obj { .. }, Some(_)) => {
+                return Err(self.error(offset, "obj files use their own materials".into()))
+            }
+            (_, None) => return Err(self.error(offset, "missing material".into())),
+            (shape, Some(mat)) => match *shape {
+                ShapeDesc::Sphere { center, radius } => Box::new(Sphere::new(center, radius, mat)),
+                ShapeDesc::MovingSphere {
+                    center0,
+                    center1,
+                    time0,
+                    time1,
+                    radius,
+                } => Box::new(MovingSphere::new(
+                    center0, center1, time0, time1, radius, mat,
+                )),
+                ShapeDesc::XyRect { x0, x1, y0, y1, k } => {
+                    Box::new(XYRect::new(x0, x1, y0, y1, k, mat))
+                }
+                ShapeDesc::XzRect { x0, x1, z0, z1, k } => {
+                    Box::new(XZRect::new(x0, x1, z0, z1, k, mat))
+                }
+                ShapeDesc::YzRect { y0, y1, z0, z1, k } => {
+                    Box::new(YZRect::new(y0, y1, z0, z1, k, mat))
+                }
+                ShapeDesc::Box { p0, p1 } => Box::new(CornellBox::new(p0, p1, mat)),
+                ShapeDesc::Triangle { vertices } => {
+                    let mesh =
+                        TriangleMesh::new(vertices.to_vec(), vec![], vec![], vec![[0, 1, 2]], mat);
+                    mesh.into_triangles().pop().unwrap()
+                }
+                ShapeDesc::Obj { .. } | ShapeDesc::Instance { .. } => unreachable!(),
+            },
+        };
+
+        if desc.flip_normals {
+            hittable = flip_normals(hittable);
+        }
+        if let Some(density) = desc.density {
+            if density <= 0. {
+                return Err(self.error(offset, "density must be positive".into()));
+            }
+            // Obj files and instances without an override have no material
+            // of their own to scatter with.
+            let phase_function = phase_function.ok_or_else(|| {
+                self.error(
+                    offset,
+                    "density needs a material for its phase function".into(),
+                )
+            })?;
+            hittable = Box::new(ConstantMedium::new(hittable, density, phase_function));
+        }
+        if let Some(medium) = &desc.medium {
+            if desc.density.is_some() {
+                return Err(self.error(offset, "expected one of `density` or `medium`".into()));
+            }
+            let field = match &medium.field {
+                FieldDesc::Noise { frequency } => DensityField::Noise {
+                    frequency: *frequency,
+                },
+                FieldDesc::Grid { path, min, max } => {
+                    DensityField::Grid(VoxelGrid::load(self.dir.join(path), *min, *max)?)
+                }
+            };
+            hittable = Box::new(HeterogeneousMedium::new(
+                hittable,
+                field,
+                medium.absorption,
+                medium.scattering,
+            ));
+        }
+        for transform in transforms {
+            hittable = match transform {
+                TransformDesc::Translate(offset) => Box::new(Translate::new(hittable, *offset)),
+                TransformDesc::RotateY(angle) => Box::new(RotateY::new(hittable, *angle)),
+                TransformDesc::RotateX(angle) => {
+                    Box::new(Transform::new(hittable, Mat4::rotate_x(*angle)))
+                }
+                TransformDesc::RotateZ(angle) => {
+                    Box::new(Transform::new(hittable, Mat4::rotate_z(*angle)))
+                }
+                TransformDesc::Scale(scale) => {
+                    self.transform(hittable, Mat4::scale(scale.factors()), offset)?
+                }
+                TransformDesc::Matrix(rows) => {
+                    self.transform(hittable, Mat4::affine(*rows), offset)?
+                }
+                TransformDesc::Keyframes(keyframes) => self.animate(hittable, keyframes, offset)?,
+            };
+        }
+        Ok(hittable)
+    }
+
+    fn transform(
+        &self,
+        hittable: Box<dyn Hittable>,
+        matrix: Mat4,
+        offset: usize,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        if matrix.inverse().is_none() {
+            return Err(self.error(offset, "transform is not invertible".into()));
+        }
+        Ok(Box::new(Transform::new(hittable, matrix)))
+    }
+
+    fn animate(
+        &self,
+        hittable: Box<dyn Hittable>,
+        descs: &[KeyframeDesc],
+        offset: usize,
+    ) -> Result<Box<dyn Hittable>, SceneError> {
+        if descs.is_empty() {
+            return Err(self.error(offset, "expected at least one keyframe".into()));
+        }
+        if descs.windows(2).any(|pair| pair[0].time >= pair[1].time) {
+            return Err(self.error(offset, "keyframe times must increase".into()));
+        }
+        let mut keyframes = Vec::with_capacity(descs.len());
+        for desc in descs {
+            let mut keyframe = Keyframe::new(desc.time);
+            keyframe.translation = desc.translate;
+            if let Some(rotate) = &desc.rotate {
+                if rotate.axis.squared_length() == 0. {
+                    return Err(self.error(offset, "rotation axis can't be zero".into()));
+                }
+                keyframe.rotation = Quat::from_axis_angle(rotate.axis, rotate.angle);
+            }
+            if let Some(scale) = desc.scale {
+                keyframe.scale = scale.factors();
+                if (0..3).any(|c| keyframe.scale[c] == 0.) {
+                    return Err(self.error(offset, "transform is not invertible".into()));
+                }
+            }
+            keyframes.push(keyframe);
+        }
+        // Scales are interpolated linearly, so one that changes sign would
+        // pass through 0 between the keyframes.
+        if keyframes
+            .windows(2)
+            .any(|pair| (0..3).any(|c| pair[0].scale[c] * pair[1].scale[c] < 0.))
+        {
+            return Err(self.error(offset, "keyframe scales can't change sign".into()));
+        }
+        Ok(Box::new(AnimatedTransform::new(hittable, keyframes)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAMERA: &str = "[camera]\nlookfrom = [0, 0, 1]\nlookat = [0, 0, 0]\n";
+
+    // Writes files into a directory of their own and loads the first as a
+    // scene.
+    fn load(test: &str, files: &[(&str, &str)]) -> Result<Scene, SceneError> {
+        let dir = std::env::temp_dir().join(format!("scene-{}-{}", test, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (name, contents) in files {
+            fs::write(dir.join(name), contents).unwrap();
+        }
+        let result = Scene::load(dir.join(files[0].0));
+        fs::remove_dir_all(&dir).unwrap();
+        result
+    }
+
+    fn parse_error(result: Result<Scene, SceneError>) -> (usize, String) {
+        match result {
+            Err(SceneError::Parse { line, msg, .. }) => (line, msg),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("loaded"),
+        }
+    }
+
+    #[test]
+    fn scene_without_objects_is_an_error() {
+        match load("empty", &[("scene.toml", CAMERA)]) {
+            Err(SceneError::Empty { .. }) => {}
+            Err(err) => panic!("{}", err),
+            Ok(_) => panic!("loaded"),
+        }
+    }
+
+    #[test]
+    fn density_without_a_material_is_an_error() {
+        let scene = format!(
+            "{}
+[[objects]]
+type = \"obj\"
+path = \"tri.obj\"
+density = 0.1
+",
+            CAMERA
+        );
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        assert_eq!(
+            parse_error(load("density", &[("scene.toml", &scene), ("tri.obj", obj)])),
+            (
+                5,
+                "density needs a material for its phase function".to_string()
+            )
+        );
+
+        // An instance without an override has only its geometry's materials.
+        let scene = format!(
+            "{}
+[materials.white]
+type = \"diffuse\"
+color = [1, 1, 1]
+
+[[geometries.ball]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+material = \"white\"
+
+[[objects]]
+type = \"instance\"
+geometry = \"ball\"
+density = 0.1
+",
+            CAMERA
+        );
+        assert_eq!(
+            parse_error(load("instance-density", &[("scene.toml", &scene)])),
+            (
+                15,
+                "density needs a material for its phase function".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn empty_image_is_an_error() {
+        let scene = format!(
+            "{}
+[render]
+width = 0
+height = 100
+
+[[objects]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+",
+            CAMERA
+        );
+        assert_eq!(
+            parse_error(load("empty-image", &[("scene.toml", &scene)])),
+            (5, "image width and height must be positive".to_string())
+        );
+    }
+
+    #[test]
+    fn unknown_shape_type_is_an_error() {
+        let scene = format!("{}\n[[objects]]\ntype = \"cone\"\nradius = 1\n", CAMERA);
+        let (line, msg) = parse_error(load("shape-type", &[("scene.toml", &scene)]));
+        assert_eq!(line, 5);
+        assert!(msg.starts_with("unknown variant `cone`"), "{}", msg);
+    }
+
+    #[test]
+    fn bad_material_field_is_an_error() {
+        let scene = format!(
+            "{}
+[materials.white]
+type = \"diffuse\"
+colour = [1, 1, 1]
+
+[[objects]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+material = \"white\"
+",
+            CAMERA
+        );
+        let (line, msg) = parse_error(load("material-field", &[("scene.toml", &scene)]));
+        assert_eq!(line, 5);
+        assert!(msg.starts_with("unknown field `colour`"), "{}", msg);
+    }
+
+    #[test]
+    fn obj_with_a_material_is_an_error() {
+        let scene = format!(
+            "{}
+[materials.white]
+type = \"diffuse\"
+color = [1, 1, 1]
+
+[[objects]]
+type = \"obj\"
+path = \"tri.obj\"
+material = \"white\"
+",
+            CAMERA
+        );
+        let obj = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\n";
+        assert_eq!(
+            parse_error(load(
+                "obj-material",
+                &[("scene.toml", &scene), ("tri.obj", obj)]
+            )),
+            (9, "obj files use their own materials".to_string())
+        );
+    }
+
+    #[test]
+    fn misspelled_keys_are_errors() {
+        // Shapes and medium fields are flattened into their object, so they
+        // have to reject what's left over themselves.
+        let scene = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0, 0, 0]\nradus = 1\n",
+            CAMERA
+        );
+        let (line, msg) = parse_error(load("shape-key", &[("scene.toml", &scene)]));
+        assert_eq!(line, 5);
+        assert!(msg.starts_with("unknown field `radus`"), "{}", msg);
+
+        let scene = format!(
+            "{}
+[[objects]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+trasnforms = [{{ translate = [1, 0, 0] }}]
+",
+            CAMERA
+        );
+        let (line, msg) = parse_error(load("object-key", &[("scene.toml", &scene)]));
+        assert_eq!(line, 5);
+        assert!(msg.starts_with("unknown field `trasnforms`"), "{}", msg);
+
+        let scene = format!(
+            "{}
+[[objects]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+medium = {{ scattering = [1, 1, 1], field = \"noise\", frequncy = 1 }}
+",
+            CAMERA
+        );
+        let (line, msg) = parse_error(load("medium-key", &[("scene.toml", &scene)]));
+        assert_eq!(line, 9);
+        assert!(msg.starts_with("unknown field `frequncy`"), "{}", msg);
+    }
+
+    #[test]
+    fn keyframe_scale_changing_sign_is_an_error() {
+        let scene = format!(
+            "{}
+[materials.white]
+type = \"diffuse\"
+color = [1, 1, 1]
+
+[[objects]]
+type = \"sphere\"
+center = [0, 0, 0]
+radius = 1
+material = \"white\"
+transforms = [{{ keyframes = [
+    {{ time = 0, scale = 1 }},
+    {{ time = 1, scale = [1, -1, 1] }},
+] }}]
+",
+            CAMERA
+        );
+        assert_eq!(
+            parse_error(load("keyframe-sign", &[("scene.toml", &scene)])),
+            (9, "keyframe scales can't change sign".to_string())
+        );
+    }
+}
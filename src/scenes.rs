@@ -0,0 +1,335 @@
+use std::path::Path;
+
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+use crate::camera::CameraSettings;
+use crate::constant_medium::ConstantMedium;
+use crate::cornellbox::CornellBox;
+use crate::heterogeneous_medium::{DensityField, HeterogeneousMedium};
+use crate::hittable::{flip_normals, Hittable};
+use crate::material::Material;
+use crate::moving_sphere::MovingSphere;
+use crate::rectangle::{XYRect, XZRect, YZRect};
+use crate::render::RenderSettings;
+use crate::rotate::RotateY;
+use crate::scene::{Scene, SceneError};
+use crate::sphere::Sphere;
+use crate::texture::Texture;
+use crate::tonemap::ToneMapper;
+use crate::translate::Translate;
+use crate::vec3::Vec3;
+
+// The spheres are placed by a generator seeded with seed. The earth texture
+// is looked for in the crate's own directory, so that it is found wherever
+// the renderer is run from.
+pub fn random_scene(seed: u64) -> Result<Vec<Box<dyn Hittable>>, SceneError> {
+    let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("earthmap.jpg");
+    let earth = Texture::image(&path).map_err(|err| SceneError::Image { path, err })?;
+
+    let n = 500;
+    let mut rng = Pcg32::seed_from_u64(seed);
+    let mut world: Vec<Box<dyn Hittable>> = Vec::with_capacity(n + 1);
+
+    let checker = Texture::checker(
+        Texture::solid((0.2, 0.3, 0.1)),
+        Texture::solid((0.9, 0.9, 0.9)),
+    );
+    world.push(Box::new(Sphere::new(
+        Vec3::new(0., -1000., 0.),
+        1000.,
+        Material::Diffuse(checker),
+    )));
+
+    for a in -11..11 {
+        for b in -11..11 {
+            let center = Vec3::new(
+                a as f32 + 0.9 * rng.gen::<f32>(),
+                0.2,
+                b as f32 + 0.9 * rng.gen::<f32>(),
+            );
+            if (center - Vec3::new(4., 0.2, 0.)).length() <= 0.9 {
+                continue;
+            }
+
+            let choose_mat = rng.gen::<f32>();
+            if choose_mat < 0.8 {
+                world.push(Box::new(MovingSphere::new(
+                    center,
+                    center + Vec3::new(0., 0.5 * rng.gen::<f32>(), 0.),
+                    0.,
+                    1.,
+                    0.2,
+                    Material::Diffuse(Texture::solid(Vec3::new(
+                        rng.gen::<f32>() * rng.gen::<f32>(),
+                        rng.gen::<f32>() * rng.gen::<f32>(),
+                        rng.gen::<f32>() * rng.gen::<f32>(),
+                    ))),
+                )));
+            } else if choose_mat < 0.95 {
+                world.push(Box::new(Sphere::new(
+                    center,
+                    0.2,
+                    Material::Metal(
+                        Vec3::new(
+                            0.5 * (1. + rng.gen::<f32>()),
+                            0.5 * (1. + rng.gen::<f32>()),
+                            0.5 * (1. + rng.gen::<f32>()),
+                        ),
+                        0.5 * rng.gen::<f32>(),
+                    ),
+                )));
+            } else {
+                world.push(Box::new(Sphere::new(center, 0.2, Material::Glass(1.5))));
+            }
+        }
+    }
+
+    world.push(Box::new(Sphere::new(
+        Vec3::new(0., 1., 0.),
+        1.,
+        Material::Glass(1.5),
+    )));
+
+    world.push(Box::new(Sphere::new(
+        Vec3::new(4., 1., 0.),
+        1.,
+        Material::Diffuse(earth),
+    )));
+
+    world.push(Box::new(Sphere::new(
+        Vec3::new(-4., 1., 0.),
+        1.,
+        Material::Metal(Vec3::new(0.7, 0.6, 0.5), 0.),
+    )));
+    Ok(world)
+}
+
+pub fn two_spheres() -> Vec<Box<dyn Hittable>> {
+    let checker = Texture::checker(
+        Texture::solid((0.2, 0.3, 0.1)),
+        Texture::solid((0.9, 0.9, 0.9)),
+    );
+    vec![
+        Box::new(Sphere::new(
+            Vec3::new(0., -10., 0.),
+            10.,
+            Material::Diffuse(checker.clone()),
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(0., 10., 0.),
+            10.,
+            Material::Diffuse(checker.clone()),
+        )),
+    ]
+}
+
+pub fn two_perlin_spheres() -> Vec<Box<dyn Hittable>> {
+    vec![
+        Box::new(Sphere::new(
+            Vec3::new(0., -1000., 0.),
+            1000.,
+            Material::Diffuse(Texture::noise(4.)),
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(0., 2., 0.),
+            2.,
+            Material::Diffuse(Texture::noise(4.)),
+        )),
+    ]
+}
+
+pub fn simple_light() -> Vec<Box<dyn Hittable>> {
+    let pertext = Texture::noise(4.);
+    let solidtext = Texture::solid((4., 4., 4.));
+    vec![
+        Box::new(Sphere::new(
+            Vec3::new(0., -1000., 0.),
+            1000.,
+            Material::Diffuse(pertext.clone()),
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(0., 2., 0.),
+            2.,
+            Material::Diffuse(pertext.clone()),
+        )),
+        Box::new(Sphere::new(
+            Vec3::new(0., 7., 0.),
+            2.,
+            Material::Light(solidtext.clone()),
+        )),
+        Box::new(XYRect::new(
+            3.,
+            5.,
+            1.,
+            3.,
+            -2.,
+            Material::Light(solidtext.clone()),
+        )),
+    ]
+}
+
+pub fn cornell_box() -> Vec<Box<dyn Hittable>> {
+    let red = Material::Diffuse(Texture::solid((0.65, 0.05, 0.05)));
+    let white = Material::Diffuse(Texture::solid((0.73, 0.73, 0.73)));
+    let green = Material::Diffuse(Texture::solid((0.12, 0.45, 0.15)));
+    let light = Material::Light(Texture::solid((15., 15., 15.)));
+
+    vec![
+        flip_normals(YZRect::new(0., 555., 0., 555., 555., green.clone())),
+        Box::new(YZRect::new(0., 555., 0., 555., 0., red.clone())),
+        Box::new(XZRect::new(213., 343., 227., 332., 554., light.clone())),
+        flip_normals(XZRect::new(0., 555., 0., 555., 555., white.clone())),
+        Box::new(XZRect::new(0., 555., 0., 555., 0., white.clone())),
+        flip_normals(XYRect::new(0., 555., 0., 555., 555., white.clone())),
+        Box::new(Translate::new(
+            RotateY::new(
+                CornellBox::new((0, 0, 0), (165, 165, 165), white.clone()),
+                -18.,
+            ),
+            (130, 0, 65),
+        )),
+        Box::new(Translate::new(
+            RotateY::new(
+                CornellBox::new((0, 0, 0), (165, 330, 165), white.clone()),
+                15.,
+            ),
+            (265, 0, 295),
+        )),
+    ]
+}
+
+// The Cornell box with its two boxes turned into blocks of smoke and fog.
+pub fn cornell_smoke() -> Vec<Box<dyn Hittable>> {
+    let red = Material::Diffuse(Texture::solid((0.65, 0.05, 0.05)));
+    let white = Material::Diffuse(Texture::solid((0.73, 0.73, 0.73)));
+    let green = Material::Diffuse(Texture::solid((0.12, 0.45, 0.15)));
+    let light = Material::Light(Texture::solid((7., 7., 7.)));
+
+    let box1 = Translate::new(
+        RotateY::new(
+            CornellBox::new((0, 0, 0), (165, 330, 165), white.clone()),
+            15.,
+        ),
+        (265, 0, 295),
+    );
+    let box2 = Translate::new(
+        RotateY::new(
+            CornellBox::new((0, 0, 0), (165, 165, 165), white.clone()),
+            -18.,
+        ),
+        (130, 0, 65),
+    );
+
+    vec![
+        flip_normals(YZRect::new(0., 555., 0., 555., 555., green)),
+        Box::new(YZRect::new(0., 555., 0., 555., 0., red)),
+        Box::new(XZRect::new(113., 443., 127., 432., 554., light)),
+        flip_normals(XZRect::new(0., 555., 0., 555., 555., white.clone())),
+        Box::new(XZRect::new(0., 555., 0., 555., 0., white.clone())),
+        flip_normals(XYRect::new(0., 555., 0., 555., 555., white)),
+        Box::new(ConstantMedium::new(
+            box1,
+            0.01,
+            Material::Isotropic(Texture::solid((0., 0., 0.))),
+        )),
+        Box::new(ConstantMedium::new(
+            box2,
+            0.01,
+            Material::Isotropic(Texture::solid((1., 1., 1.))),
+        )),
+    ]
+}
+
+// The Cornell box with a cloud of turbulent smoke in place of the boxes.
+pub fn cornell_cloud() -> Vec<Box<dyn Hittable>> {
+    let red = Material::Diffuse(Texture::solid((0.65, 0.05, 0.05)));
+    let white = Material::Diffuse(Texture::solid((0.73, 0.73, 0.73)));
+    let green = Material::Diffuse(Texture::solid((0.12, 0.45, 0.15)));
+    let light = Material::Light(Texture::solid((15., 15., 15.)));
+
+    vec![
+        flip_normals(YZRect::new(0., 555., 0., 555., 555., green)),
+        Box::new(YZRect::new(0., 555., 0., 555., 0., red)),
+        Box::new(XZRect::new(213., 343., 227., 332., 554., light)),
+        flip_normals(XZRect::new(0., 555., 0., 555., 555., white.clone())),
+        Box::new(XZRect::new(0., 555., 0., 555., 0., white.clone())),
+        flip_normals(XYRect::new(0., 555., 0., 555., 555., white.clone())),
+        Box::new(HeterogeneousMedium::new(
+            Sphere::new(Vec3::new(278., 250., 278.), 180., white),
+            DensityField::Noise { frequency: 0.01 },
+            Vec3::new(0.002, 0.004, 0.006),
+            Vec3::new(0.05, 0.05, 0.05),
+        )),
+    ]
+}
+
+// Names and descriptions of the scenes available through builtin.
+pub const SCENES: &[(&str, &str)] = &[
+    (
+        "random",
+        "Random spheres around a glass, metal and textured sphere",
+    ),
+    ("two_spheres", "Two checker-textured spheres"),
+    (
+        "two_perlin_spheres",
+        "Two spheres with Perlin noise textures",
+    ),
+    (
+        "simple_light",
+        "Perlin spheres lit by a sphere and a rectangle light",
+    ),
+    ("cornell_box", "The Cornell box with two rotated boxes"),
+    (
+        "cornell_smoke",
+        "The Cornell box with blocks of smoke and fog",
+    ),
+    (
+        "cornell_cloud",
+        "The Cornell box with a cloud of turbulent smoke",
+    ),
+];
+
+// The scene called name, rendered with seed, which also lays out the random
+// scene, or None if there is no such scene.
+pub fn builtin(name: &str, seed: u64) -> Result<Option<Scene>, SceneError> {
+    let camera = match name {
+        "cornell_box" | "cornell_smoke" | "cornell_cloud" => {
+            CameraSettings::new((278., 278., -800.), (278., 278., 0.), 40.)
+        }
+        "simple_light" => CameraSettings::new((26., 3., 6.), (0., 2., 0.), 20.),
+        _ => CameraSettings::new((13., 2., 3.), (0., 0., 0.), 20.),
+    };
+    let world = match name {
+        "random" => random_scene(seed)?,
+        "two_spheres" => two_spheres(),
+        "two_perlin_spheres" => two_perlin_spheres(),
+        "simple_light" => simple_light(),
+        "cornell_box" => cornell_box(),
+        "cornell_smoke" => cornell_smoke(),
+        "cornell_cloud" => cornell_cloud(),
+        _ => return Ok(None),
+    };
+    Ok(Some(Scene {
+        world,
+        camera,
+        render: RenderSettings {
+            seed,
+            ..RenderSettings::default()
+        },
+        tonemap: ToneMapper::default(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_listed_scene_builds() {
+        for (name, _) in SCENES {
+            assert!(builtin(name, 0).unwrap().is_some(), "{}", name);
+        }
+        assert!(builtin("no_such_scene", 0).unwrap().is_none());
+    }
+}
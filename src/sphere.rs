@@ -2,6 +2,7 @@ use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::material::Material;
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 #[derive(Clone)]
@@ -41,6 +42,8 @@ impl Hittable for Sphere {
                     mat: &self.mat,
                     u,
                     v,
+                    id: 0,
+                    weight: Vec3::new(1., 1., 1.),
                 });
             }
 
@@ -55,6 +58,8 @@ impl Hittable for Sphere {
                     mat: &self.mat,
                     u,
                     v,
+                    id: 0,
+                    weight: Vec3::new(1., 1., 1.),
                 });
             }
         }
@@ -68,6 +73,22 @@ impl Hittable for Sphere {
             self.center + Vec3::new(self.radius, self.radius, self.radius),
         ))
     }
+
+    fn is_emissive(&self) -> bool {
+        self.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        if self.hit(&Ray::new(o, v, time), 0.001, f32::MAX).is_some() {
+            cone_pdf(self.center, self.radius, o)
+        } else {
+            0.
+        }
+    }
+
+    fn random(&self, o: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        random_to_sphere(self.center, self.radius, o, sampler)
+    }
 }
 
 pub fn get_sphere_uv(p: Vec3) -> (f32, f32) {
@@ -78,3 +99,43 @@ pub fn get_sphere_uv(p: Vec3) -> (f32, f32) {
     let v = (theta + PI / 2.) / PI;
     (u, v)
 }
+
+// Solid angle density of sampling a direction uniformly within the cone that
+// a sphere subtends from o.
+pub fn cone_pdf(center: Vec3, radius: f32, o: Vec3) -> f32 {
+    let distance_squared = (center - o).squared_length();
+    if distance_squared <= radius * radius {
+        // Inside the sphere every direction hits it.
+        return 1. / (4. * std::f32::consts::PI);
+    }
+    let cos_theta_max = (1. - radius * radius / distance_squared).sqrt();
+    1. / (2. * std::f32::consts::PI * (1. - cos_theta_max))
+}
+
+pub fn random_to_sphere(center: Vec3, radius: f32, o: Vec3, sampler: &mut dyn Sampler) -> Vec3 {
+    use std::f32::consts::PI;
+    let direction = center - o;
+    let distance_squared = direction.squared_length();
+    let (r1, r2) = sampler.get_2d();
+    let phi = 2. * PI * r1;
+    if distance_squared <= radius * radius {
+        let z = 1. - 2. * r2;
+        let r = (1. - z * z).max(0.).sqrt();
+        return Vec3::new(r * phi.cos(), r * phi.sin(), z);
+    }
+
+    // Build an orthonormal basis around the direction to the center.
+    let w = direction.unit_vector();
+    let a = if w.x().abs() > 0.9 {
+        Vec3::new(0., 1., 0.)
+    } else {
+        Vec3::new(1., 0., 0.)
+    };
+    let v = w.cross(a).unit_vector();
+    let u = w.cross(v);
+
+    let cos_theta_max = (1. - radius * radius / distance_squared).sqrt();
+    let z = 1. + r2 * (cos_theta_max - 1.);
+    let sin_theta = (1. - z * z).max(0.).sqrt();
+    sin_theta * phi.cos() * u + sin_theta * phi.sin() * v + z * w
+}
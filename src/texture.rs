@@ -1,3 +1,6 @@
+use std::path::Path;
+use std::sync::Arc;
+
 use crate::perlin;
 use crate::vec3::Vec3;
 
@@ -8,7 +11,8 @@ pub enum Texture {
         even: Box<Texture>,
     },
     Image {
-        data: Vec<u8>,
+        // Shared, so that cloning a material doesn't copy the pixels.
+        data: Arc<[u8]>,
         w: u32,
         h: u32,
     },
@@ -28,6 +32,16 @@ impl Texture {
         }
     }
 
+    pub fn image<P: AsRef<Path>>(path: P) -> image::ImageResult<Texture> {
+        let img = image::open(path)?.to_rgb();
+        let (w, h) = img.dimensions();
+        Ok(Texture::Image {
+            data: img.into_raw().into(),
+            w,
+            h,
+        })
+    }
+
     pub fn noise(scale: f32) -> Texture {
         Texture::Noise { scale }
     }
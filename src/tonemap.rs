@@ -0,0 +1,196 @@
+use serde::Deserialize;
+
+use crate::vec3::Vec3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operator {
+    Clamp,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    Hable,
+}
+
+// Maps linear scene radiance to display values in [0, 1]. exposure is in
+// stops, and white is the luminance that the extended Reinhard operator maps
+// to 1.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToneMapper {
+    pub operator: Operator,
+    pub exposure: f32,
+    pub white: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper {
+            operator: Operator::Clamp,
+            exposure: 0.,
+            white: 4.,
+        }
+    }
+}
+
+pub fn luminance(color: Vec3) -> f32 {
+    0.2126 * color[0] + 0.7152 * color[1] + 0.0722 * color[2]
+}
+
+// Clamps to [0, 1], sending NaN to 0.
+pub fn saturate(x: f32) -> f32 {
+    if x > 0. {
+        x.min(1.)
+    } else {
+        0.
+    }
+}
+
+// Filmic curve from Uncharted 2, without the white point normalization.
+// Like aces, it's clamped at 0, below which it eventually turns up to white.
+fn hable_partial(x: f32) -> f32 {
+    let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+    let x = x.max(0.);
+    (x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f) - e / f
+}
+
+// Narkowicz's fit of the ACES reference rendering transform. The rational
+// curve turns back up below 0, so negative values, as from filters with
+// negative lobes, are clamped first rather than mapped to white.
+fn aces(x: f32) -> f32 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    let x = x.max(0.);
+    x * (a * x + b) / (x * (c * x + d) + e)
+}
+
+// The sRGB transfer function, from linear light to encoded values.
+pub fn srgb_encode(x: f32) -> f32 {
+    if x <= 0.003_130_8 {
+        12.92 * x
+    } else {
+        1.055 * x.powf(1. / 2.4) - 0.055
+    }
+}
+
+impl ToneMapper {
+    pub fn new(operator: Operator) -> ToneMapper {
+        ToneMapper {
+            operator,
+            ..ToneMapper::default()
+        }
+    }
+
+    // The display-linear color for a radiance value, before sRGB encoding.
+    pub fn map(&self, color: Vec3) -> Vec3 {
+        let color = color * 2f32.powf(self.exposure);
+        let mapped = match self.operator {
+            Operator::Clamp => color,
+            // Both Reinhard operators compress luminance and scale the color
+            // to match, which keeps hues from shifting towards white.
+            Operator::Reinhard | Operator::ExtendedReinhard => {
+                let l = luminance(color);
+                if l <= 0. {
+                    return Vec3::default();
+                }
+                let scale = if self.operator == Operator::Reinhard {
+                    1. / (1. + l)
+                } else {
+                    (1. + l / (self.white * self.white)) / (1. + l)
+                };
+                color * scale
+            }
+            // The fit was made for an exposure about 1.7 times too bright.
+            Operator::Aces => {
+                let color = 0.6 * color;
+                Vec3::new(aces(color[0]), aces(color[1]), aces(color[2]))
+            }
+            Operator::Hable => {
+                let exposure_bias = 2.;
+                let scale = 1. / hable_partial(11.2);
+                let f = |x: f32| hable_partial(exposure_bias * x) * scale;
+                Vec3::new(f(color[0]), f(color[1]), f(color[2]))
+            }
+        };
+        Vec3::new(
+            saturate(mapped[0]),
+            saturate(mapped[1]),
+            saturate(mapped[2]),
+        )
+    }
+
+    // Tone maps and sRGB encodes a radiance value to 8 bits per channel.
+    pub fn to_rgb8(&self, color: Vec3) -> [u8; 3] {
+        let color = self.map(color);
+        let encode = |x: f32| (srgb_encode(x) * 255. + 0.5) as u8;
+        [encode(color[0]), encode(color[1]), encode(color[2])]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grey(x: f32) -> Vec3 {
+        Vec3::new(x, x, x)
+    }
+
+    #[test]
+    fn srgb_encode_is_continuous_and_monotonic() {
+        assert_eq!(srgb_encode(0.), 0.);
+        assert!((srgb_encode(1.) - 1.).abs() < 1e-6);
+        // The linear and power segments meet at the threshold.
+        let x: f32 = 0.003_130_8;
+        assert!((12.92 * x - (1.055 * x.powf(1. / 2.4) - 0.055)).abs() < 1e-5);
+        assert!((srgb_encode(x) - srgb_encode(x + 1e-6)).abs() < 1e-4);
+
+        let mut last = srgb_encode(0.);
+        for i in 1..=10_000 {
+            let y = srgb_encode(i as f32 / 10_000.);
+            assert!(y > last);
+            last = y;
+        }
+    }
+
+    #[test]
+    fn reinhard_maps_one_to_half() {
+        let mapped = ToneMapper::new(Operator::Reinhard).map(grey(1.));
+        assert!((mapped - grey(0.5)).length() < 1e-6);
+    }
+
+    #[test]
+    fn extended_reinhard_maps_white_to_one() {
+        for &white in &[1., 4., 11.2] {
+            let tonemap = ToneMapper {
+                white,
+                ..ToneMapper::new(Operator::ExtendedReinhard)
+            };
+            assert!((tonemap.map(grey(white)) - grey(1.)).length() < 1e-5);
+            assert!(tonemap.map(grey(0.9 * white))[0] < 1.);
+        }
+    }
+
+    #[test]
+    fn hable_maps_its_white_point_to_one() {
+        // The curve is normalised at 11.2 after the exposure bias of 2.
+        let tonemap = ToneMapper::new(Operator::Hable);
+        assert!((tonemap.map(grey(5.6)) - grey(1.)).length() < 1e-5);
+        assert!(tonemap.map(grey(5.))[0] < 1.);
+        assert!(tonemap.map(grey(0.))[0] < 1e-6);
+        assert!(tonemap.map(grey(-10.))[0] < 1e-6);
+    }
+
+    #[test]
+    fn aces_stays_within_the_unit_range() {
+        let tonemap = ToneMapper::new(Operator::Aces);
+        let mut last = 0.;
+        for i in 0..=1000 {
+            let x = 1.02f32.powi(i) - 1.;
+            let y = tonemap.map(grey(x))[0];
+            assert!((0. ..=1.).contains(&y));
+            assert!(y >= last);
+            last = y;
+        }
+        assert_eq!(tonemap.map(grey(f32::NAN))[0], 0.);
+        assert_eq!(tonemap.map(grey(-1.))[0], 0.);
+    }
+}
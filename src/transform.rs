@@ -0,0 +1,223 @@
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::mat4::Mat4;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+// Places a hittable by an arbitrary affine matrix, which can rotate about any
+// axis, scale and shear as well as translate. Rays are taken into object space
+// by the inverse without being renormalized, so hit distances carry over
+// unchanged.
+pub struct Transform {
+    hittable: Box<dyn Hittable>,
+    matrix: Mat4,
+    inverse: Mat4,
+    // The inverse transpose, which keeps normals perpendicular to surfaces
+    // that have been scaled or sheared.
+    normal_matrix: Mat4,
+}
+
+impl Transform {
+    // Panics if matrix isn't invertible.
+    pub fn new(hittable: impl 'static + Hittable, matrix: Mat4) -> Transform {
+        let inverse = matrix
+            .inverse()
+            .expect("transform matrix is not invertible");
+        Transform {
+            hittable: Box::new(hittable),
+            matrix,
+            inverse,
+            normal_matrix: inverse.transpose(),
+        }
+    }
+}
+
+// The smallest box around bbox after it is transformed by matrix.
+pub fn transform_box(matrix: &Mat4, bbox: &AABB) -> AABB {
+    let mut min = Vec3::new(f32::MAX, f32::MAX, f32::MAX);
+    let mut max = Vec3::new(f32::MIN, f32::MIN, f32::MIN);
+    for i in 0..8 {
+        let corner = Vec3::new(
+            if i & 1 == 0 { bbox.min[0] } else { bbox.max[0] },
+            if i & 2 == 0 { bbox.min[1] } else { bbox.max[1] },
+            if i & 4 == 0 { bbox.min[2] } else { bbox.max[2] },
+        );
+        let p = matrix.transform_point(corner);
+        for c in 0..3 {
+            min[c] = min[c].min(p[c]);
+            max[c] = max[c].max(p[c]);
+        }
+    }
+    AABB::new(min, max)
+}
+
+// The hittable methods of a hittable placed by matrix, with the inverse and
+// normal matrix that go with it, shared with AnimatedTransform.
+pub(crate) fn hit_transformed<'a>(
+    hittable: &'a dyn Hittable,
+    matrix: &Mat4,
+    inverse: &Mat4,
+    normal_matrix: &Mat4,
+    r: &Ray,
+    t_min: f32,
+    t_max: f32,
+) -> Option<HitRecord<'a>> {
+    let object_r = Ray::new(
+        inverse.transform_point(r.origin()),
+        inverse.transform_vector(r.direction()),
+        r.time(),
+    );
+    hittable.hit(&object_r, t_min, t_max).map(|rec| HitRecord {
+        p: matrix.transform_point(rec.p),
+        normal: normal_matrix.transform_vector(rec.normal).unit_vector(),
+        ..rec
+    })
+}
+
+pub(crate) fn pdf_value_transformed(
+    hittable: &dyn Hittable,
+    inverse: &Mat4,
+    o: Vec3,
+    v: Vec3,
+    time: f32,
+) -> f32 {
+    // Scaling and shearing squeeze some directions together and spread
+    // others apart, so the density is converted by the Jacobian of the map
+    // from world to object directions, |det A| / |A w|^3 for the unit
+    // direction w.
+    let object_v = inverse.transform_vector(v);
+    let ratio = v.length() / object_v.length();
+    let jacobian = inverse.determinant3().abs() * ratio * ratio * ratio;
+    hittable.pdf_value(inverse.transform_point(o), object_v, time) * jacobian
+}
+
+pub(crate) fn random_transformed(
+    hittable: &dyn Hittable,
+    matrix: &Mat4,
+    inverse: &Mat4,
+    o: Vec3,
+    time: f32,
+    sampler: &mut dyn Sampler,
+) -> Vec3 {
+    let object_o = inverse.transform_point(o);
+    matrix.transform_vector(hittable.random(object_o, time, sampler))
+}
+
+impl Hittable for Transform {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        hit_transformed(
+            &*self.hittable,
+            &self.matrix,
+            &self.inverse,
+            &self.normal_matrix,
+            r,
+            t_min,
+            t_max,
+        )
+    }
+
+    fn bounding_box(&self, t0: f32, t1: f32) -> Option<AABB> {
+        self.hittable
+            .bounding_box(t0, t1)
+            .map(|bbox| transform_box(&self.matrix, &bbox))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.hittable.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        pdf_value_transformed(&*self.hittable, &self.inverse, o, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        random_transformed(
+            &*self.hittable,
+            &self.matrix,
+            &self.inverse,
+            o,
+            time,
+            sampler,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Material;
+    use crate::rectangle::XZRect;
+    use crate::sampler::SamplerKind;
+    use crate::sphere::Sphere;
+    use crate::texture::Texture;
+
+    // A rotation, shear and uneven scale, which leave no angles alone.
+    fn matrix() -> Mat4 {
+        let shear = Mat4::affine([[1., 0.7, 0., 0.], [0., 1., 0., 0.], [0., -0.4, 1., 0.]]);
+        Mat4::translate((0.5, 1., -0.5)) * Mat4::rotate_z(25.) * shear * Mat4::scale((3., 0.5, 1.5))
+    }
+
+    fn light() -> Material {
+        Material::Light(Texture::solid((1., 1., 1.)))
+    }
+
+    fn plane_point(x: f32, z: f32) -> Vec3 {
+        matrix().transform_point(Vec3::new(x, 0., z))
+    }
+
+    // A plane stays a plane, so the normals at its hits must be perpendicular
+    // to the lines between them.
+    #[test]
+    fn normals_stay_perpendicular() {
+        let plane = Transform::new(XZRect::new(-1., 1., -1., 1., 0., light()), matrix());
+        let o = Vec3::new(0.3, 6., 0.2);
+        let hits: Vec<_> = [(0., 0.), (0.3, 0.), (0., 0.2), (-0.2, -0.1)]
+            .iter()
+            .map(|&(x, z)| {
+                let r = Ray::new(o, plane_point(x, z) - o, 0.);
+                plane.hit(&r, 0., f32::MAX).expect("plane missed")
+            })
+            .collect();
+        for rec in &hits {
+            assert!((rec.normal.length() - 1.).abs() < 1e-5);
+            for other in &hits {
+                let along = other.p - rec.p;
+                assert!(rec.normal.dot(along).abs() < 1e-4 * (1. + along.length()));
+            }
+        }
+    }
+
+    // pdf_value is a density over directions, so it integrates to 1 over the
+    // sphere of them however the light is stretched; without the Jacobian the
+    // object space density would be off by the change in solid angle.
+    #[test]
+    fn pdf_integrates_to_one() {
+        let ellipsoid = Transform::new(Sphere::new(Vec3::new(0., 0., 0.), 1., light()), matrix());
+        let o = Vec3::new(2., 6., 3.);
+
+        // Midpoints of a grid over the unit sphere in z and azimuth, each cell
+        // covering an equal solid angle.
+        let (nz, nphi) = (800, 1600);
+        let cell = 4. * std::f32::consts::PI / (nz * nphi) as f32;
+        let mut integral = 0.;
+        for i in 0..nz {
+            let z = 1. - 2. * (i as f32 + 0.5) / nz as f32;
+            let r = (1. - z * z).sqrt();
+            for j in 0..nphi {
+                let phi = 2. * std::f32::consts::PI * (j as f32 + 0.5) / nphi as f32;
+                let v = Vec3::new(r * phi.cos(), r * phi.sin(), z);
+                integral += ellipsoid.pdf_value(o, v, 0.) as f64 * cell as f64;
+            }
+        }
+        assert!((integral - 1.).abs() < 0.02, "integral {}", integral);
+
+        // And the directions random picks are ones the density covers.
+        let mut sampler = SamplerKind::Independent.build(0, 1);
+        for i in 0..100 {
+            sampler.start_pixel_sample(0, i);
+            let v = ellipsoid.random(o, 0., &mut *sampler);
+            assert!(ellipsoid.pdf_value(o, v, 0.) > 0.);
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::aabb::AABB;
 use crate::hittable::{HitRecord, Hittable};
 use crate::ray::Ray;
+use crate::sampler::Sampler;
 use crate::vec3::Vec3;
 
 pub struct Translate {
@@ -33,4 +34,16 @@ impl Hittable for Translate {
             .bounding_box(t0, t1)
             .map(|bbox| AABB::new(bbox.min + self.offset, bbox.max + self.offset))
     }
+
+    fn is_emissive(&self) -> bool {
+        self.hittable.is_emissive()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        self.hittable.pdf_value(o - self.offset, v, time)
+    }
+
+    fn random(&self, o: Vec3, time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        self.hittable.random(o - self.offset, time, sampler)
+    }
 }
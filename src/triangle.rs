@@ -0,0 +1,280 @@
+use std::sync::Arc;
+
+use crate::aabb::AABB;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::sampler::Sampler;
+use crate::vec3::Vec3;
+
+// Vertex data is stored once per mesh and shared by every face. normals and
+// uvs are either empty or hold one entry per position; each face indexes all
+// three buffers with the same index.
+pub struct TriangleMesh {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f32, f32)>,
+    pub indices: Vec<[usize; 3]>,
+    pub mat: Material,
+}
+
+impl TriangleMesh {
+    pub fn new(
+        positions: Vec<Vec3>,
+        normals: Vec<Vec3>,
+        uvs: Vec<(f32, f32)>,
+        indices: Vec<[usize; 3]>,
+        mat: Material,
+    ) -> TriangleMesh {
+        assert!(normals.is_empty() || normals.len() == positions.len());
+        assert!(uvs.is_empty() || uvs.len() == positions.len());
+        assert!(indices.iter().flatten().all(|&i| i < positions.len()));
+        TriangleMesh {
+            positions,
+            normals,
+            uvs,
+            indices,
+            mat,
+        }
+    }
+
+    // Split the mesh into one hittable per face, ready to be handed to BVH::new.
+    pub fn into_triangles(self) -> Vec<Box<dyn Hittable>> {
+        let mesh = Arc::new(self);
+        (0..mesh.indices.len())
+            .map(|face| Box::new(Triangle::new(mesh.clone(), face)) as Box<dyn Hittable>)
+            .collect()
+    }
+}
+
+pub struct Triangle {
+    mesh: Arc<TriangleMesh>,
+    face: usize,
+}
+
+impl Triangle {
+    pub fn new(mesh: Arc<TriangleMesh>, face: usize) -> Triangle {
+        assert!(face < mesh.indices.len());
+        Triangle { mesh, face }
+    }
+
+    fn vertices(&self) -> (Vec3, Vec3, Vec3) {
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let p = &self.mesh.positions;
+        (p[i0], p[i1], p[i2])
+    }
+}
+
+impl Hittable for Triangle {
+    fn hit<'a>(&'a self, r: &Ray, t_min: f32, t_max: f32) -> Option<HitRecord<'a>> {
+        // Moller-Trumbore intersection, giving the barycentric coordinates
+        // (b1, b2) of the hit point alongside t.
+        let (p0, p1, p2) = self.vertices();
+        let e1 = p1 - p0;
+        let e2 = p2 - p0;
+        let pvec = r.direction().cross(e2);
+        let det = e1.dot(pvec);
+        if det.abs() < 1e-8 {
+            return None;
+        }
+        let inv_det = 1. / det;
+
+        let tvec = r.origin() - p0;
+        let b1 = tvec.dot(pvec) * inv_det;
+        if !(0. ..=1.).contains(&b1) {
+            return None;
+        }
+
+        let qvec = tvec.cross(e1);
+        let b2 = r.direction().dot(qvec) * inv_det;
+        if b2 < 0. || b1 + b2 > 1. {
+            return None;
+        }
+
+        let t = e2.dot(qvec) * inv_det;
+        if t < t_min || t > t_max {
+            return None;
+        }
+
+        let b0 = 1. - b1 - b2;
+        let [i0, i1, i2] = self.mesh.indices[self.face];
+        let normal = if self.mesh.normals.is_empty() {
+            e1.cross(e2).unit_vector()
+        } else {
+            let n = &self.mesh.normals;
+            (b0 * n[i0] + b1 * n[i1] + b2 * n[i2]).unit_vector()
+        };
+        let (u, v) = if self.mesh.uvs.is_empty() {
+            (b1, b2)
+        } else {
+            let uv = &self.mesh.uvs;
+            (
+                b0 * uv[i0].0 + b1 * uv[i1].0 + b2 * uv[i2].0,
+                b0 * uv[i0].1 + b1 * uv[i1].1 + b2 * uv[i2].1,
+            )
+        };
+
+        Some(HitRecord {
+            t,
+            p: r.point_at_parameter(t),
+            normal,
+            mat: &self.mesh.mat,
+            u,
+            v,
+            id: 0,
+            weight: Vec3::new(1., 1., 1.),
+        })
+    }
+
+    fn bounding_box(&self, _t0: f32, _t1: f32) -> Option<AABB> {
+        // Pad every axis so axis-aligned faces don't produce a zero-width box.
+        let (p0, p1, p2) = self.vertices();
+        let mut min = p0;
+        let mut max = p0;
+        for p in &[p1, p2] {
+            for c in 0..3 {
+                min[c] = min[c].min(p[c]);
+                max[c] = max[c].max(p[c]);
+            }
+        }
+        let pad = Vec3::new(0.0001, 0.0001, 0.0001);
+        Some(AABB::new(min - pad, max + pad))
+    }
+
+    fn is_emissive(&self) -> bool {
+        self.mesh.mat.is_light()
+    }
+
+    fn pdf_value(&self, o: Vec3, v: Vec3, time: f32) -> f32 {
+        // Use the geometric normal, since the shading normal in the hit
+        // record says nothing about the projected area.
+        match self.hit(&Ray::new(o, v, time), 0.001, f32::MAX) {
+            Some(rec) => {
+                let (p0, p1, p2) = self.vertices();
+                let n = (p1 - p0).cross(p2 - p0);
+                let area = 0.5 * n.length();
+                let distance_squared = rec.t * rec.t * v.squared_length();
+                let cosine = (v.dot(n) / (v.length() * n.length())).abs();
+                distance_squared / (cosine * area)
+            }
+            None => 0.,
+        }
+    }
+
+    fn random(&self, o: Vec3, _time: f32, sampler: &mut dyn Sampler) -> Vec3 {
+        // Uniformly distributed barycentric coordinates.
+        let (u, v) = sampler.get_2d();
+        let su = u.sqrt();
+        let (b1, b2) = (1. - su, v * su);
+        let (p0, p1, p2) = self.vertices();
+        (1. - b1 - b2) * p0 + b1 * p1 + b2 * p2 - o
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+    use crate::texture::Texture;
+
+    // The right triangle (0, 0, 0), (2, 0, 0), (0, 2, 0), facing +z.
+    fn triangle(normals: Vec<Vec3>, uvs: Vec<(f32, f32)>) -> Triangle {
+        let positions = vec![
+            Vec3::new(0., 0., 0.),
+            Vec3::new(2., 0., 0.),
+            Vec3::new(0., 2., 0.),
+        ];
+        let mat = Material::Diffuse(Texture::solid((0.5, 0.5, 0.5)));
+        let mesh = TriangleMesh::new(positions, normals, uvs, vec![[0, 1, 2]], mat);
+        Triangle::new(Arc::new(mesh), 0)
+    }
+
+    fn close(a: Vec3, b: Vec3) -> bool {
+        (a - b).length() < 1e-5
+    }
+
+    #[test]
+    fn hit_gives_the_point_normal_and_barycentrics() {
+        let tri = triangle(Vec::new(), Vec::new());
+        let r = Ray::new(Vec3::new(0.5, 1., 3.), Vec3::new(0., 0., -1.), 0.);
+        let rec = tri.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!((rec.t - 3.).abs() < 1e-5);
+        assert!(close(rec.p, Vec3::new(0.5, 1., 0.)));
+        assert!(close(rec.normal, Vec3::new(0., 0., 1.)));
+        // Without uvs, (u, v) are the weights of the second and third vertex.
+        assert!((rec.u - 0.25).abs() < 1e-5);
+        assert!((rec.v - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn misses() {
+        let tri = triangle(Vec::new(), Vec::new());
+        let down = Vec3::new(0., 0., -1.);
+        // Past the hypotenuse, and past each of the other two edges.
+        for &(x, y) in &[(1.5, 1.5), (-0.1, 1.), (1., -0.1)] {
+            let r = Ray::new(Vec3::new(x, y, 1.), down, 0.);
+            assert!(tri.hit(&r, 0.001, f32::MAX).is_none());
+        }
+        // Parallel to the plane of the triangle.
+        let r = Ray::new(Vec3::new(-1., 0.5, 0.), Vec3::new(1., 0., 0.), 0.);
+        assert!(tri.hit(&r, 0.001, f32::MAX).is_none());
+        // Behind the origin, and beyond t_max.
+        let r = Ray::new(Vec3::new(0.5, 0.5, -1.), down, 0.);
+        assert!(tri.hit(&r, 0.001, f32::MAX).is_none());
+        let r = Ray::new(Vec3::new(0.5, 0.5, 1.), down, 0.);
+        assert!(tri.hit(&r, 0.001, 0.9).is_none());
+        assert!(tri.hit(&r, 1.1, f32::MAX).is_none());
+    }
+
+    #[test]
+    fn hit_interpolates_vertex_normals_and_uvs() {
+        let normals = vec![
+            Vec3::new(0., 0., 1.),
+            Vec3::new(1., 0., 0.),
+            Vec3::new(0., 1., 0.),
+        ];
+        let uvs = vec![(0.1, 0.2), (0.9, 0.2), (0.1, 0.8)];
+        let tri = triangle(normals, uvs);
+        // The point with barycentric coordinates (0.5, 0.25, 0.25).
+        let r = Ray::new(Vec3::new(0.5, 0.5, 1.), Vec3::new(0., 0., -1.), 0.);
+        let rec = tri.hit(&r, 0.001, f32::MAX).unwrap();
+        assert!(close(rec.normal, Vec3::new(0.25, 0.25, 0.5).unit_vector()));
+        assert!((rec.u - 0.3).abs() < 1e-5);
+        assert!((rec.v - 0.35).abs() < 1e-5);
+    }
+
+    #[test]
+    fn bounding_box_of_an_axis_aligned_face_is_padded() {
+        let bbox = triangle(Vec::new(), Vec::new())
+            .bounding_box(0., 1.)
+            .unwrap();
+        assert!(bbox.min.z() < 0. && bbox.max.z() > 0.);
+        assert!(close(bbox.min, Vec3::new(-0.0001, -0.0001, -0.0001)));
+        assert!(close(bbox.max, Vec3::new(2.0001, 2.0001, 0.0001)));
+    }
+
+    #[test]
+    fn random_directions_hit_the_triangle_with_its_pdf() {
+        let tri = triangle(Vec::new(), Vec::new());
+        let o = Vec3::new(0.5, 0.5, 3.);
+        let n = 4096;
+        let mut sampler = SamplerKind::Independent.build(0, n);
+        let mut sum = Vec3::new(0., 0., 0.);
+        for i in 0..n {
+            sampler.start_pixel_sample(0, i);
+            let v = tri.random(o, 0., &mut *sampler);
+            let p = o + v;
+            assert!(p.z().abs() < 1e-5);
+            assert!(p.x() >= -1e-5 && p.y() >= -1e-5 && p.x() + p.y() <= 2. + 1e-5);
+            sum += p;
+
+            // Solid angle density: distance squared over cosine times area.
+            let cosine = v.z().abs() / v.length();
+            let expected = v.squared_length() / (cosine * 2.);
+            let pdf = tri.pdf_value(o, v, 0.);
+            assert!((pdf - expected).abs() < 1e-3 * expected);
+        }
+        // Uniform over the area, so centred on the centroid.
+        assert!((sum / n as f32 - Vec3::new(2. / 3., 2. / 3., 0.)).length() < 0.03);
+    }
+}
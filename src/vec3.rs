@@ -2,7 +2,9 @@ use std::ops::{
     Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign,
 };
 
-#[derive(Clone, Copy, Debug, Default)]
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
 pub struct Vec3(pub [f32; 3]);
 
 #[rustfmt::skip]
@@ -23,7 +25,7 @@ impl Vec3 {
     }
 
     pub fn squared_length(&self) -> f32 {
-        (self.0[0] * self.0[0] + self.0[1] * self.0[1] + self.0[2] * self.0[2])
+        self.0[0] * self.0[0] + self.0[1] * self.0[1] + self.0[2] * self.0[2]
     }
 
     pub fn make_unit_vector(&mut self) {
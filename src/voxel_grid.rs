@@ -0,0 +1,220 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::vec3::Vec3;
+
+#[derive(Debug)]
+pub enum GridError {
+    Io {
+        path: PathBuf,
+        err: io::Error,
+    },
+    Parse {
+        path: PathBuf,
+        line: usize,
+        msg: String,
+    },
+}
+
+impl fmt::Display for GridError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GridError::Io { path, err } => write!(f, "{}: {}", path.display(), err),
+            GridError::Parse { path, line, msg } => {
+                write!(f, "{}:{}: {}", path.display(), line, msg)
+            }
+        }
+    }
+}
+
+impl Error for GridError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            GridError::Io { err, .. } => Some(err),
+            GridError::Parse { .. } => None,
+        }
+    }
+}
+
+// A 3D grid of density values stretched over the box from min to max, with
+// the values at voxel centers and trilinear interpolation between them.
+pub struct VoxelGrid {
+    size: [usize; 3],
+    values: Vec<f32>,
+    min: Vec3,
+    max: Vec3,
+    // The largest value, which lookups are normalized by.
+    peak: f32,
+}
+
+impl VoxelGrid {
+    // values holds size[0] * size[1] * size[2] entries with x varying fastest,
+    // then y, then z. Negative values are treated as 0.
+    pub fn new(size: [usize; 3], values: Vec<f32>, min: Vec3, max: Vec3) -> VoxelGrid {
+        assert_eq!(values.len(), size[0] * size[1] * size[2]);
+        let values: Vec<f32> = values.into_iter().map(|v| v.max(0.)).collect();
+        let peak = values.iter().copied().fold(0., f32::max);
+        VoxelGrid {
+            size,
+            values,
+            min,
+            max,
+            peak,
+        }
+    }
+
+    // Reads a text file holding the three dimensions of the grid followed by
+    // its values, in the order new expects, all separated by whitespace.
+    // Lines starting with # are comments.
+    pub fn load<P: AsRef<Path>>(path: P, min: Vec3, max: Vec3) -> Result<VoxelGrid, GridError> {
+        let path = path.as_ref();
+        let source = fs::read_to_string(path).map_err(|err| GridError::Io {
+            path: path.to_owned(),
+            err,
+        })?;
+        let error = |line, msg| GridError::Parse {
+            path: path.to_owned(),
+            line,
+            msg,
+        };
+
+        let mut numbers = source
+            .lines()
+            .enumerate()
+            .filter(|(_, line)| !line.trim_start().starts_with('#'))
+            .flat_map(|(idx, line)| line.split_whitespace().map(move |word| (idx + 1, word)));
+        let last_line = source.lines().count().max(1);
+
+        let mut size = [0; 3];
+        let mut count: usize = 1;
+        for n in &mut size {
+            let (line, word) = numbers
+                .next()
+                .ok_or_else(|| error(last_line, "expected the grid dimensions".into()))?;
+            *n = match word.parse() {
+                Ok(n) if n > 0 => n,
+                _ => return Err(error(line, format!("invalid grid dimension `{}`", word))),
+            };
+            count = count
+                .checked_mul(*n)
+                .ok_or_else(|| error(line, "grid dimensions are too large".into()))?;
+        }
+
+        // The values are counted as they're read rather than trusting the
+        // dimensions with an allocation up front.
+        let mut values = Vec::new();
+        for (line, word) in &mut numbers {
+            if values.len() == count {
+                return Err(error(line, format!("more than {} values", count)));
+            }
+            let value = word
+                .parse()
+                .map_err(|_| error(line, format!("invalid value `{}`", word)))?;
+            values.push(value);
+        }
+        if values.len() < count {
+            return Err(error(
+                last_line,
+                format!("expected {} values, found {}", count, values.len()),
+            ));
+        }
+        Ok(VoxelGrid::new(size, values, min, max))
+    }
+
+    fn at(&self, x: usize, y: usize, z: usize) -> f32 {
+        self.values[(z * self.size[1] + y) * self.size[0] + x]
+    }
+
+    // The density at p relative to the peak of the grid, so within [0, 1].
+    // Points outside the box have none.
+    pub fn value(&self, p: Vec3) -> f32 {
+        if self.peak <= 0. {
+            return 0.;
+        }
+        let mut index = [0; 3];
+        let mut fraction = [0.; 3];
+        for c in 0..3 {
+            let extent = self.max[c] - self.min[c];
+            let t = (p[c] - self.min[c]) / extent;
+            if !(0. ..=1.).contains(&t) {
+                return 0.;
+            }
+            let n = self.size[c];
+            let g = (t * n as f32 - 0.5).clamp(0., (n - 1) as f32);
+            index[c] = (g as usize).min(n.saturating_sub(2));
+            fraction[c] = if n > 1 { g - index[c] as f32 } else { 0. };
+        }
+
+        // Weight of the lower or upper neighbour along one axis.
+        let corner = |d: usize, fraction: f32| if d == 1 { fraction } else { 1. - fraction };
+        let mut sum = 0.;
+        for dz in 0..2 {
+            for dy in 0..2 {
+                for dx in 0..2 {
+                    let (x, y, z) = (index[0] + dx, index[1] + dy, index[2] + dz);
+                    if x >= self.size[0] || y >= self.size[1] || z >= self.size[2] {
+                        continue;
+                    }
+                    let weight =
+                        corner(dx, fraction[0]) * corner(dy, fraction[1]) * corner(dz, fraction[2]);
+                    sum += weight * self.at(x, y, z);
+                }
+            }
+        }
+        sum / self.peak
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(test: &str, source: &str) -> Result<VoxelGrid, GridError> {
+        let path = std::env::temp_dir().join(format!("grid-{}-{}.txt", test, std::process::id()));
+        fs::write(&path, source).unwrap();
+        let result = VoxelGrid::load(&path, Vec3::new(0., 0., 0.), Vec3::new(1., 1., 1.));
+        fs::remove_file(&path).unwrap();
+        result
+    }
+
+    fn parse_error(result: Result<VoxelGrid, GridError>) -> (usize, String) {
+        match result {
+            Err(GridError::Parse { line, msg, .. }) => (line, msg),
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("loaded"),
+        }
+    }
+
+    #[test]
+    fn loads_values_after_comments() {
+        let grid = load("ok", "# a 2x1x1 grid\n2 1 1\n0 4\n").unwrap();
+        assert_eq!(grid.size, [2, 1, 1]);
+        assert_eq!(grid.values, vec![0., 4.]);
+        assert_eq!(grid.peak, 4.);
+    }
+
+    #[test]
+    fn overflowing_dimensions_are_an_error() {
+        let big = usize::MAX / 2;
+        let source = format!("{} 1\n3\n0\n", big);
+        assert_eq!(
+            parse_error(load("overflow", &source)),
+            (2, "grid dimensions are too large".to_string())
+        );
+    }
+
+    #[test]
+    fn value_count_must_match_the_dimensions() {
+        assert_eq!(
+            parse_error(load("few", "2 2 1\n0 1\n2\n")),
+            (3, "expected 4 values, found 3".to_string())
+        );
+        assert_eq!(
+            parse_error(load("many", "2 1 1\n0 1\n2\n")),
+            (3, "more than 2 values".to_string())
+        );
+    }
+}